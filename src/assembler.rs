@@ -0,0 +1,689 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::chip8::LOAD_START;
+
+// Nested includes and macro expansions allowed, to stop on recursion
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Line 0 is for errors about the whole file
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Source line after includes and macros are expanded
+#[derive(Clone, Debug)]
+struct Line {
+    file: Rc<str>,
+    number: usize,
+    text: String,
+}
+
+impl Line {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => {
+                let long = operands
+                    .iter()
+                    .any(|operand| keyword(operand, "long").is_some());
+                if long {
+                    4
+                } else {
+                    2
+                }
+            }
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => 2 * values.len(),
+        }
+    }
+}
+
+// Value of a constant, evaluated when first used
+enum Symbol {
+    Address(usize),
+    Constant(String, usize),
+}
+
+#[derive(Default)]
+struct Assembler {
+    lines: Vec<Line>,
+    macros: HashMap<String, Macro>,
+    // Macro being defined, with the line it starts at
+    definition: Option<(String, Macro, Line)>,
+    expansions: usize,
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && register(s).is_none()
+}
+
+// `VX` register number
+fn register(s: &str) -> Option<u16> {
+    let s = s.trim();
+    let digit = s.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(digit, 16).ok()
+}
+
+// Rest of `s` after a leading case-insensitive keyword
+fn keyword<'a>(s: &'a str, word: &str) -> Option<&'a str> {
+    let s = s.trim();
+    let (head, tail) = s.split_at_checked(word.len())?;
+    (head.eq_ignore_ascii_case(word) && (tail.is_empty() || tail.starts_with(char::is_whitespace)))
+        .then_some(tail.trim())
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let s = s.replace('_', "");
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn split_operands(s: &str) -> Vec<String> {
+    if s.trim().is_empty() {
+        return Vec::new();
+    }
+    s.split(',')
+        .map(|operand| operand.trim().to_string())
+        .collect()
+}
+
+// Replace whole words of `text` found in `substitutions`
+fn substitute(text: &str, substitutions: &HashMap<&str, &str>) -> String {
+    let mut out = String::new();
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once('\n')) {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            word.push(c);
+            continue;
+        }
+        out.push_str(substitutions.get(word.as_str()).unwrap_or(&word.as_str()));
+        word.clear();
+        out.push(c);
+    }
+    out.pop();
+    out
+}
+
+impl Assembler {
+    fn include(
+        &mut self,
+        file: &str,
+        source: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let file: Rc<str> = file.into();
+        for (index, text) in source.lines().enumerate() {
+            let line = Line {
+                file: file.clone(),
+                number: index + 1,
+                text: text.to_string(),
+            };
+            self.preprocess(line, dir, depth)?;
+        }
+        Ok(())
+    }
+
+    // Handle macro definitions, macro calls and includes
+    fn preprocess(&mut self, mut line: Line, dir: &Path, depth: usize) -> Result<(), AsmError> {
+        if depth > MAX_DEPTH {
+            return Err(line.error("includes or macros nested too deeply"));
+        }
+        // Strip comments
+        if let Some(index) = line.text.find(';') {
+            line.text.truncate(index);
+        }
+        let text = line.text.trim().to_string();
+
+        if let Some((_, definition, _)) = self.definition.as_mut() {
+            if keyword(&text, "endm").is_some() {
+                let (name, definition, _) = self.definition.take().unwrap();
+                self.macros.insert(name, definition);
+            } else {
+                definition.body.push(text);
+            }
+            return Ok(());
+        }
+
+        if let Some(rest) = keyword(&text, "macro") {
+            let (name, params) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if !is_identifier(name) {
+                return Err(line.error(format!("invalid macro name '{}'", name)));
+            }
+            let params = split_operands(params);
+            if let Some(param) = params.iter().find(|param| !is_identifier(param)) {
+                return Err(line.error(format!("invalid macro parameter '{}'", param)));
+            }
+            let definition = Macro {
+                params,
+                body: Vec::new(),
+            };
+            self.definition = Some((name.to_string(), definition, line));
+            return Ok(());
+        }
+
+        if let Some(path) = keyword(&text, "include") {
+            let path = path
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .ok_or_else(|| line.error("expected include \"FILE\""))?;
+            let path = dir.join(path);
+            let source = fs::read_to_string(&path)
+                .map_err(|e| line.error(format!("cannot read '{}': {}", path.display(), e)))?;
+            let dir = path.parent().unwrap_or(dir).to_path_buf();
+            return self.include(&path.display().to_string(), &source, &dir, depth + 1);
+        }
+
+        // Macro calls may follow a label
+        let (label, statement) = match text.split_once(':') {
+            Some((label, statement)) if is_identifier(label.trim()) => {
+                (Some(label.trim()), statement.trim())
+            }
+            _ => (None, text.as_str()),
+        };
+        let (name, args) = statement
+            .split_once(char::is_whitespace)
+            .unwrap_or((statement, ""));
+        if let Some(definition) = self.macros.get(name) {
+            let (params, body) = (definition.params.clone(), definition.body.clone());
+            let args = split_operands(args);
+            if args.len() != params.len() {
+                return Err(line.error(format!(
+                    "macro {} expects {} arguments, got {}",
+                    name,
+                    params.len(),
+                    args.len()
+                )));
+            }
+            // `\@` makes labels unique to each expansion
+            self.expansions += 1;
+            let unique = format!("_{}", self.expansions);
+            let substitutions: HashMap<&str, &str> = params
+                .iter()
+                .map(String::as_str)
+                .zip(args.iter().map(String::as_str))
+                .collect();
+            let body: Vec<String> = body
+                .iter()
+                .map(|text| substitute(text, &substitutions).replace("\\@", &unique))
+                .collect();
+            if let Some(label) = label {
+                self.lines.push(Line {
+                    text: format!("{}:", label),
+                    ..line.clone()
+                });
+            }
+            for text in body {
+                let expanded = Line {
+                    text,
+                    ..line.clone()
+                };
+                self.preprocess(expanded, dir, depth + 1)?;
+            }
+            return Ok(());
+        }
+
+        self.lines.push(Line { text, ..line });
+        Ok(())
+    }
+
+    fn assemble(self) -> Result<Vec<u8>, AsmError> {
+        if let Some((name, _, line)) = self.definition {
+            return Err(line.error(format!("macro {} is missing endm", name)));
+        }
+
+        // First pass: addresses of labels and constant definitions
+        let mut symbols = HashMap::new();
+        let mut statements = Vec::new();
+        let mut address = LOAD_START;
+        for line in &self.lines {
+            let mut text = line.text.as_str();
+            while let Some((label, rest)) = text.split_once(':') {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    break;
+                }
+                if symbols
+                    .insert(label.to_string(), Symbol::Address(address))
+                    .is_some()
+                {
+                    return Err(line.error(format!("'{}' is defined twice", label)));
+                }
+                text = rest.trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            if let Some(value) = keyword(rest, "equ") {
+                if !is_identifier(first) {
+                    return Err(line.error(format!("invalid constant name '{}'", first)));
+                }
+                let constant = Symbol::Constant(value.to_string(), address);
+                if symbols.insert(first.to_string(), constant).is_some() {
+                    return Err(line.error(format!("'{}' is defined twice", first)));
+                }
+                continue;
+            }
+            let statement = match first.to_ascii_lowercase().as_str() {
+                "db" => Statement::Bytes(split_operands(rest)),
+                "dw" => Statement::Words(split_operands(rest)),
+                mnemonic => Statement::Instruction {
+                    mnemonic: mnemonic.to_string(),
+                    operands: split_operands(rest),
+                },
+            };
+            let size = statement.size();
+            statements.push((line, address, statement));
+            address += size;
+        }
+
+        // Second pass: encoding
+        let mut bytes = Vec::new();
+        for (line, address, statement) in statements {
+            let context = Context {
+                symbols: &symbols,
+                address,
+                line,
+            };
+            match statement {
+                Statement::Bytes(values) => {
+                    for value in values {
+                        bytes.push(context.value(&value, -128, 0xff)? as u8);
+                    }
+                }
+                Statement::Words(values) => {
+                    for value in values {
+                        let value = context.value(&value, -0x8000, 0xffff)?;
+                        bytes.extend(value.to_be_bytes());
+                    }
+                }
+                Statement::Instruction { mnemonic, operands } => {
+                    bytes.extend(context.encode(&mnemonic, &operands)?);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+// What a statement needs to evaluate its operands
+struct Context<'a> {
+    symbols: &'a HashMap<String, Symbol>,
+    address: usize,
+    line: &'a Line,
+}
+
+impl Context<'_> {
+    // Evaluate terms separated by `+` and `-`, `$` being the current address
+    fn evaluate(&self, expression: &str, depth: usize) -> Result<i64, AsmError> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Err(self.line.error("missing operand"));
+        }
+        if expression.ends_with(['+', '-']) {
+            return Err(self
+                .line
+                .error(format!("invalid expression '{}'", expression)));
+        }
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        let mut expect_term = true;
+        for c in expression.chars().chain(std::iter::once('+')) {
+            match c {
+                '+' | '-' if expect_term && term.trim().is_empty() => {
+                    if c == '-' {
+                        sign = -sign;
+                    }
+                }
+                '+' | '-' => {
+                    total += sign * self.term(term.trim(), depth)?;
+                    term.clear();
+                    sign = if c == '-' { -1 } else { 1 };
+                    expect_term = true;
+                }
+                _ => {
+                    term.push(c);
+                    expect_term = false;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, depth: usize) -> Result<i64, AsmError> {
+        if term == "$" {
+            return Ok(self.address as i64);
+        }
+        if let Some(number) = parse_number(term) {
+            return Ok(number);
+        }
+        match self.symbols.get(term) {
+            Some(Symbol::Address(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(expression, address)) if depth < MAX_DEPTH => Context {
+                address: *address,
+                ..*self
+            }
+            .evaluate(expression, depth + 1),
+            Some(Symbol::Constant(..)) => Err(self
+                .line
+                .error(format!("'{}' is defined recursively", term))),
+            None if term.is_empty() => Err(self.line.error("missing operand")),
+            None => Err(self.line.error(format!("unknown symbol '{}'", term))),
+        }
+    }
+
+    fn value(&self, expression: &str, min: i64, max: i64) -> Result<u16, AsmError> {
+        let value = self.evaluate(expression, 0)?;
+        if !(min..=max).contains(&value) {
+            return Err(self
+                .line
+                .error(format!("{} is out of range for '{}'", value, expression)));
+        }
+        Ok(value as u16)
+    }
+
+    fn register(&self, operand: &str) -> Result<u16, AsmError> {
+        register(operand).ok_or_else(|| {
+            self.line
+                .error(format!("expected a register, got '{}'", operand))
+        })
+    }
+
+    fn encode(&self, mnemonic: &str, operands: &[String]) -> Result<Vec<u8>, AsmError> {
+        let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+        let upper: Vec<String> = operands.iter().map(|o| o.to_ascii_uppercase()).collect();
+        let upper: Vec<&str> = upper.iter().map(String::as_str).collect();
+        let is_register = |operand: &str| register(operand).is_some();
+        let address = |operand| self.value(operand, 0, 0xfff);
+        let byte = |operand| Ok::<_, AsmError>(self.value(operand, -128, 0xff)? & 0xff);
+        let nibble = |operand| self.value(operand, 0, 0xf);
+        let x = |operand| self.register(operand).map(|x| x << 8);
+        let xy = |first, second| Ok::<_, AsmError>(x(first)? | self.register(second)? << 4);
+
+        let opcode = match (mnemonic, &upper[..]) {
+            ("cls", []) => 0x00e0,
+            ("ret", []) => 0x00ee,
+            ("scd", [_]) => 0x00c0 | nibble(operands[0])?,
+            ("scu", [_]) => 0x00d0 | nibble(operands[0])?,
+            ("scr", []) => 0x00fb,
+            ("scl", []) => 0x00fc,
+            ("exit", []) => 0x00fd,
+            ("low", []) => 0x00fe,
+            ("high", []) => 0x00ff,
+            ("jp", ["V0", _]) => 0xb000 | address(operands[1])?,
+            ("jp", [_]) => 0x1000 | address(operands[0])?,
+            ("call", [_]) => 0x2000 | address(operands[0])?,
+            ("se", [vx, vy]) if is_register(vx) && is_register(vy) => {
+                0x5000 | xy(operands[0], operands[1])?
+            }
+            ("se", [_, _]) => 0x3000 | x(operands[0])? | byte(operands[1])?,
+            ("sne", [vx, vy]) if is_register(vx) && is_register(vy) => {
+                0x9000 | xy(operands[0], operands[1])?
+            }
+            ("sne", [_, _]) => 0x4000 | x(operands[0])? | byte(operands[1])?,
+            ("save", [_, _]) => 0x5002 | xy(operands[0], operands[1])?,
+            ("load", [_, _]) => 0x5003 | xy(operands[0], operands[1])?,
+            ("ld", ["I", long]) if keyword(long, "long").is_some() => {
+                let target = self.value(keyword(operands[1], "long").unwrap(), 0, 0xffff)?;
+                return Ok(vec![0xf0, 0x00, (target >> 8) as u8, target as u8]);
+            }
+            ("ld", ["I", _]) => 0xa000 | address(operands[1])?,
+            ("ld", [_, "DT"]) => 0xf007 | x(operands[0])?,
+            ("ld", [_, "K"]) => 0xf00a | x(operands[0])?,
+            ("ld", ["DT", _]) => 0xf015 | x(operands[1])?,
+            ("ld", ["ST", _]) => 0xf018 | x(operands[1])?,
+            ("ld", ["F", _]) => 0xf029 | x(operands[1])?,
+            ("ld", ["HF", _]) => 0xf030 | x(operands[1])?,
+            ("ld", ["B", _]) => 0xf033 | x(operands[1])?,
+            ("ld", ["[I]", _]) => 0xf055 | x(operands[1])?,
+            ("ld", [_, "[I]"]) => 0xf065 | x(operands[0])?,
+            ("ld", ["R", _]) => 0xf075 | x(operands[1])?,
+            ("ld", [_, "R"]) => 0xf085 | x(operands[0])?,
+            ("ld", [_, vy]) if is_register(vy) => 0x8000 | xy(operands[0], operands[1])?,
+            ("ld", [_, _]) => 0x6000 | x(operands[0])? | byte(operands[1])?,
+            ("add", ["I", _]) => 0xf01e | x(operands[1])?,
+            ("add", [_, vy]) if is_register(vy) => 0x8004 | xy(operands[0], operands[1])?,
+            ("add", [_, _]) => 0x7000 | x(operands[0])? | byte(operands[1])?,
+            ("or", [_, _]) => 0x8001 | xy(operands[0], operands[1])?,
+            ("and", [_, _]) => 0x8002 | xy(operands[0], operands[1])?,
+            ("xor", [_, _]) => 0x8003 | xy(operands[0], operands[1])?,
+            ("sub", [_, _]) => 0x8005 | xy(operands[0], operands[1])?,
+            ("subn", [_, _]) => 0x8007 | xy(operands[0], operands[1])?,
+            // The source register defaults to the destination
+            ("shr", [_]) => 0x8006 | xy(operands[0], operands[0])?,
+            ("shr", [_, _]) => 0x8006 | xy(operands[0], operands[1])?,
+            ("shl", [_]) => 0x800e | xy(operands[0], operands[0])?,
+            ("shl", [_, _]) => 0x800e | xy(operands[0], operands[1])?,
+            ("rnd", [_, _]) => 0xc000 | x(operands[0])? | byte(operands[1])?,
+            ("drw", [_, _, _]) => 0xd000 | xy(operands[0], operands[1])? | nibble(operands[2])?,
+            ("skp", [_]) => 0xe09e | x(operands[0])?,
+            ("sknp", [_]) => 0xe0a1 | x(operands[0])?,
+            ("plane", [_]) => 0xf001 | self.value(operands[0], 0, 3)? << 8,
+            ("audio", []) => 0xf002,
+            ("pitch", [_]) => 0xf03a | x(operands[0])?,
+            _ => {
+                return Err(self.line.error(format!(
+                    "invalid instruction '{} {}'",
+                    mnemonic.to_ascii_uppercase(),
+                    operands.join(", ")
+                )))
+            }
+        };
+        Ok(u16::to_be_bytes(opcode).to_vec())
+    }
+}
+
+// Assemble `source`, loaded at 0x200, resolving includes from the current directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.include("<source>", source, Path::new("."), 0)?;
+    assembler.assemble()
+}
+
+// Assemble a file, resolving includes relative to it
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    let dir = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    let mut assembler = Assembler::default();
+    assembler.include(&path.display().to_string(), &source, &dir, 0)?;
+    assembler.assemble()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn instructions() {
+        let source = "
+            CLS
+            LD V0, 10        ; decimal
+            ld va, vb
+            LD I, LONG 0x1234
+            DRW V0, V1, 0xF
+            SHR V3
+            JP V0, 0x300
+            LD [I], VF
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x00, 0xe0, 0x60, 0x0a, 0x8a, 0xb0, 0xf0, 0x00, 0x12, 0x34, 0xd0, 0x1f, 0x83, 0x36,
+                0xb3, 0x00, 0xff, 0x55
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_constants_and_data() {
+        let source = "
+            SPEED equ 2 + OFFSET
+            OFFSET equ 1
+            start:  LD I, sprite
+                    ADD V0, SPEED
+                    JP start
+            sprite: db 0b10000001, 0xFF, -1
+            table:  dw sprite, $ - 2
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0xa2, 0x06, 0x70, 0x03, 0x12, 0x00, 0x81, 0xff, 0xff, 0x02, 0x06, 0x02, 0x07]
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+            macro wait reg, frames
+                LD reg, frames
+                LD DT, reg
+            loop\\@:
+                LD reg, DT
+                SE reg, 0
+                JP loop\\@
+            endm
+            wait V1, 30
+            here: wait V2, 0x10
+        ";
+        let bytes = assemble(source).unwrap();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(
+            bytes[..10],
+            [0x61, 0x1e, 0xf1, 0x15, 0xf1, 0x07, 0x31, 0x00, 0x12, 0x04]
+        );
+        assert_eq!(bytes[18..], [0x12, 0x0e]);
+    }
+
+    #[test]
+    fn include_files() {
+        let dir = std::env::temp_dir().join(format!("crab8-asm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.asm"), "include \"lib.asm\"\nJP 0x202\n").unwrap();
+        fs::write(dir.join("lib.asm"), "CLS\nBAD\n").unwrap();
+        let error = assemble_file(dir.join("main.asm")).unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error.file.ends_with("lib.asm"));
+        fs::write(dir.join("lib.asm"), "CLS\n").unwrap();
+        assert_eq!(
+            assemble_file(dir.join("main.asm")).unwrap(),
+            [0x00, 0xe0, 0x12, 0x02]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("CLS\nJP nowhere"),
+            "<source>:2: unknown symbol 'nowhere'"
+        );
+        assert_eq!(
+            error("LD V0, 256"),
+            "<source>:1: 256 is out of range for '256'"
+        );
+        assert_eq!(error("a:\na:"), "<source>:2: 'a' is defined twice");
+        assert_eq!(
+            error("DRW V0, 1, 2"),
+            "<source>:1: expected a register, got '1'"
+        );
+        assert_eq!(
+            error("MOV V0, V1"),
+            "<source>:1: invalid instruction 'MOV V0, V1'"
+        );
+        assert_eq!(
+            error("x equ x\nLD V0, x"),
+            "<source>:2: 'x' is defined recursively"
+        );
+        assert_eq!(error("macro m\nCLS"), "<source>:1: macro m is missing endm");
+    }
+
+    #[test]
+    fn disassembly_roundtrip() {
+        for rom in [
+            &include_bytes!("../roms/test_opcode.ch8")[..],
+            include_bytes!("../roms/pong2.ch8"),
+        ] {
+            let listing = disassemble(rom).to_string();
+            assert_eq!(assemble(&listing).unwrap(), rom);
+        }
+    }
+
+    #[test]
+    fn overlapping_code_roundtrip() {
+        // LD V0, 0x12; JP 0x201, jumping inside the first instruction
+        let rom = [0x60, 0x12, 0x12, 0x01];
+        let listing = disassemble(&rom).to_string();
+        assert!(listing.contains("JP 0x201"), "{}", listing);
+        assert_eq!(assemble(&listing).unwrap(), rom);
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::chip8::Chip8;
+use crate::timer::TIMER_FREQUENCY;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+pub const BEEP_FREQUENCY: f32 = 440.0;
+// Bits per second of an XO-CHIP pattern at the default pitch
+const PATTERN_RATE: f32 = 4000.0;
+const VOLUME: f32 = 0.25;
+
+// Destination of the generated mono samples, in [-1.0, 1.0]
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn write(&mut self, samples: &[f32]);
+}
+
+// Square wave generator, producing one 60 Hz frame of samples at a time
+pub struct Beeper {
+    sample_rate: u32,
+    frequency: f32,
+    // Position in the current period, in [0, 1)
+    phase: f32,
+}
+
+impl Beeper {
+    pub fn new(sample_rate: u32, frequency: f32) -> Self {
+        Self {
+            sample_rate,
+            frequency,
+            phase: 0.0,
+        }
+    }
+
+    pub fn frame(&mut self, active: bool) -> Vec<f32> {
+        let len = (self.sample_rate / TIMER_FREQUENCY) as usize;
+        if !active {
+            // Restart the wave on next beep to avoid a click
+            self.phase = 0.0;
+            return vec![0.0; len];
+        }
+
+        let step = self.frequency / self.sample_rate as f32;
+        (0..len)
+            .map(|_| {
+                let sample = if self.phase < 0.5 { VOLUME } else { -VOLUME };
+                self.phase = (self.phase + step).fract();
+                sample
+            })
+            .collect()
+    }
+
+    // Play the 128 bits of an XO-CHIP audio pattern in a loop, at the rate set by FX3A
+    pub fn pattern_frame(&mut self, pattern: &[u8; 16], pitch: u8) -> Vec<f32> {
+        let len = (self.sample_rate / TIMER_FREQUENCY) as usize;
+        let rate = PATTERN_RATE * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+        // Phase covers the whole pattern here
+        let step = rate / 128.0 / self.sample_rate as f32;
+        (0..len)
+            .map(|_| {
+                let bit = (self.phase * 128.0) as usize;
+                let sample = if pattern[bit / 8] >> (7 - bit % 8) & 1 != 0 {
+                    VOLUME
+                } else {
+                    -VOLUME
+                };
+                self.phase = (self.phase + step).fract();
+                sample
+            })
+            .collect()
+    }
+}
+
+// Beep while the sound timer is running
+pub struct Audio {
+    beeper: Beeper,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Audio {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            beeper: Beeper::new(sink.sample_rate(), BEEP_FREQUENCY),
+            sink,
+        }
+    }
+
+    // To be called once per frame, before the timers tick, so that a sound
+    // timer set to N beeps for N frames
+    pub fn frame(&mut self, chip: &Chip8) {
+        let active = chip.sound_timer() > 0;
+        let samples = match chip.audio_pattern() {
+            Some(pattern) if active => self.beeper.pattern_frame(&pattern, chip.pitch()),
+            _ => self.beeper.frame(active),
+        };
+        self.sink.write(&samples);
+    }
+}
+
+// Discard samples, only keeping count of them
+#[derive(Default)]
+pub struct NullSink {
+    pub samples: usize,
+    pub audible: usize,
+}
+
+impl AudioSink for NullSink {
+    fn write(&mut self, samples: &[f32]) {
+        self.samples += samples.len();
+        self.audible += samples.iter().filter(|&&s| s != 0.0).count();
+    }
+}
+
+// 16 bits mono PCM WAV file. Sizes in the header are patched on `finish` or drop.
+pub struct WavSink<W: Write + Seek> {
+    // Only taken out by `finish`
+    writer: Option<W>,
+    data_len: u32,
+}
+
+const WAV_HEADER_LEN: u32 = 44;
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let byte_rate = SAMPLE_RATE * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_LEN - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // Mono
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Block align
+        writer.write_all(&16u16.to_le_bytes())?; // Bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer: Some(writer),
+            data_len: 0,
+        })
+    }
+
+    // Write final sizes in the header and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut writer = self.writer.take().unwrap();
+        Self::update_header(&mut writer, self.data_len)?;
+        Ok(writer)
+    }
+
+    fn update_header(writer: &mut W, data_len: u32) -> io::Result<()> {
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        writer.seek(SeekFrom::Start(40))?;
+        writer.write_all(&data_len.to_le_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn write(&mut self, samples: &[f32]) {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|&s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+        if let Some(writer) = self.writer.as_mut() {
+            match writer.write_all(&bytes) {
+                Ok(_) => self.data_len += bytes.len() as u32,
+                Err(e) => eprintln!("Failed to write audio samples: {}", e),
+            }
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = Self::update_header(writer, self.data_len);
+        }
+    }
+}
+
+// Play samples on the default output device
+#[cfg(feature = "cpal")]
+pub struct CpalSink {
+    buffer: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>,
+    sample_rate: u32,
+    // Playback stops when the stream is dropped
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "cpal")]
+impl CpalSink {
+    pub fn new() -> Result<Self, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+        use std::{
+            collections::VecDeque,
+            sync::{Arc, Mutex},
+        };
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let config = device
+            .default_output_config()
+            .map_err(|e| e.to_string())?
+            .config();
+        let channels = config.channels as usize;
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let source = Arc::clone(&buffer);
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    let mut source = source.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(source.pop_front().unwrap_or(0.0));
+                    }
+                },
+                |e| eprintln!("Audio stream error: {}", e),
+                None,
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            buffer,
+            sample_rate: config.sample_rate.0,
+            _stream: stream,
+        })
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl AudioSink for CpalSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        // Drop stale samples rather than letting latency grow
+        let max_len = (self.sample_rate / 10) as usize;
+        if buffer.len() > max_len {
+            buffer.clear();
+        }
+        buffer.extend(samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    #[test]
+    fn beeper_square_wave() {
+        let mut beeper = Beeper::new(8000, 1000.0);
+        let samples = beeper.frame(true);
+        assert_eq!(samples.len(), 133);
+        // 8 samples per period, half high and half low
+        assert_eq!(
+            samples[..8],
+            [VOLUME, VOLUME, VOLUME, VOLUME, -VOLUME, -VOLUME, -VOLUME, -VOLUME]
+        );
+        assert!(beeper.frame(false).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn pattern_playback() {
+        // At the default pitch and 8000 Hz, each bit lasts 2 samples
+        let mut beeper = Beeper::new(8000, BEEP_FREQUENCY);
+        let mut pattern = [0u8; 16];
+        pattern[0] = 0b1010_0000;
+        let samples = beeper.pattern_frame(&pattern, 64);
+        assert_eq!(
+            samples[..8],
+            [VOLUME, VOLUME, -VOLUME, -VOLUME, VOLUME, VOLUME, -VOLUME, -VOLUME]
+        );
+        assert!(samples[8..].iter().all(|&s| s == -VOLUME));
+        // One octave up doubles the rate
+        let mut beeper = Beeper::new(8000, BEEP_FREQUENCY);
+        let samples = beeper.pattern_frame(&pattern, 64 + 48);
+        assert_eq!(samples[..4], [VOLUME, -VOLUME, VOLUME, -VOLUME]);
+    }
+
+    // Null sink whose counts stay readable once handed over to `Audio`
+    struct SharedSink(Rc<RefCell<NullSink>>);
+
+    impl AudioSink for SharedSink {
+        fn write(&mut self, samples: &[f32]) {
+            self.0.borrow_mut().write(samples);
+        }
+    }
+
+    // Audible frames out of 4, for a sound timer set to `length`
+    fn beep_frames(length: u8) -> usize {
+        // V0 := length; sound := V0; loop
+        let mut chip = Chip8::default();
+        chip.load(&[0x60, length, 0xf0, 0x18, 0x12, 0x04]).unwrap();
+        chip.step().unwrap();
+        chip.step().unwrap();
+
+        let sink = Rc::new(RefCell::new(NullSink::default()));
+        let mut audio = Audio::new(Box::new(SharedSink(sink.clone())));
+        for _ in 0..4 {
+            audio.frame(&chip);
+            chip.tick_timers();
+        }
+
+        let sink = sink.borrow();
+        assert_eq!(sink.samples, 4 * 735);
+        sink.audible / 735
+    }
+
+    #[test]
+    fn beep_follows_sound_timer() {
+        assert_eq!(beep_frames(1), 1);
+        assert_eq!(beep_frames(2), 2);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut sink = WavSink::new(Cursor::new(Vec::new())).unwrap();
+        sink.write(&[0.0, 1.0, -1.0]);
+        let wav = sink.finish().unwrap().into_inner();
+
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 6);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 6);
+        assert_eq!(&wav[44..], [0x00, 0x00, 0xff, 0x7f, 0x01, 0x80]);
+    }
+}
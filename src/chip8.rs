@@ -1,11 +1,8 @@
-use rand::{thread_rng, Rng};
-use rand_distr::{Distribution, Normal};
-use std::{
-    hint::spin_loop,
-    time::{Duration, Instant},
-    usize,
-};
-use winit::event::ElementState;
+use rand::Rng;
+
+use crate::error::Chip8Error;
+use crate::quirks::{LoadStore, Quirks};
+use crate::state::{StateReader, StateWriter};
 
 const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -26,14 +23,43 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// SUPER-CHIP 8x10 digits, used by FX30
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+// Low resolution display
 pub const W_HEIGHT: usize = 32;
 pub const W_WIDTH: usize = 64;
 
-const MEMORY_SIZE: usize = 4096;
+// SUPER-CHIP high resolution display
+pub const HIRES_HEIGHT: usize = 64;
+pub const HIRES_WIDTH: usize = 128;
+
+// XO-CHIP extends the address space to 64 KiB
+const MEMORY_SIZE: usize = 0x10000;
 const FONT_OFFSET: usize = 0x050;
-const LOAD_START: usize = 0x200;
+const BIG_FONT_OFFSET: usize = FONT_OFFSET + FONT.len();
+pub const LOAD_START: usize = 0x200;
+const STACK_SIZE: usize = 16;
 
-const DEFAULT_KEYS: &str = "1234qwerasdfzxcv";
+// FX3A value playing audio patterns at 4000 Hz
+pub const DEFAULT_PITCH: u8 = 64;
 
 #[derive(Clone, Copy, Debug)]
 enum KeyState {
@@ -44,24 +70,65 @@ enum KeyState {
 #[derive(Debug)]
 pub struct Chip8 {
     memory: [u8; MEMORY_SIZE],
-    pixels: [[bool; W_WIDTH]; W_HEIGHT],
+    // Sized for high resolution, only the top left corner is used in low resolution.
+    // Each pixel is a bitmask of the XO-CHIP planes it is lit on.
+    pixels: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+    // XO-CHIP planes affected by drawing, clearing and scrolling (FN01)
+    planes: u8,
+    hires: bool,
     pc: usize,
     i_register: u16,
     v_registers: [u8; 16],
     stack: Vec<usize>,
     delay_timer: u8,
     sound_timer: u8,
-    keys: String,
     keys_states: [KeyState; 16],
+    // Key pressed while blocked on FX0A, waiting for its release
+    waiting_key: Option<u8>,
+    // Keys already held when blocking on FX0A, ignored until released
+    held_keys: Option<[bool; 16]>,
+    // SUPER-CHIP persistent flags (FX75/FX85)
+    rpl_flags: [u8; 16],
+    // XO-CHIP audio pattern (F002), played instead of the beep when set
+    audio_pattern: Option<[u8; 16]>,
+    // XO-CHIP playback rate (FX3A)
+    pitch: u8,
+    // Set by 00FD, the machine doesn't execute anything after
+    exited: bool,
+    quirks: Quirks,
+    // Set on each 60 Hz tick, cleared by drawing when waiting for display
+    vblank: bool,
 }
 
 #[derive(Debug)]
-struct Instruction(u16, u16, u16, u16);
+pub(crate) struct Instruction(
+    pub(crate) u16,
+    pub(crate) u16,
+    pub(crate) u16,
+    pub(crate) u16,
+);
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum Target {
     Memory,
     Pixels,
+    // Program stopped itself
+    Exit,
+}
+
+impl Instruction {
+    pub(crate) fn decode(instruction: u16) -> Self {
+        let nibble_1 = ((0xf << 12) & instruction) >> 12;
+        let nibble_2 = ((0xf << 8) & instruction) >> 8;
+        let nibble_3 = ((0xf << 4) & instruction) >> 4;
+        let nibble_4 = 0xf & instruction;
+
+        Instruction(nibble_1, nibble_2, nibble_3, nibble_4)
+    }
+
+    pub(crate) fn opcode(&self) -> u16 {
+        (self.0 << 12) | (self.1 << 8) | (self.2 << 4) | self.3
+    }
 }
 
 impl From<&Instruction> for u8 {
@@ -84,59 +151,118 @@ impl From<&Instruction> for usize {
 
 // private method
 impl Chip8 {
-    fn compute<F>(&mut self, source: u16, other: u16, operation: F, trigger: bool)
+    // 8XY4, 8XY5 and 8XY7: VX gets the result before VF gets the carry or no
+    // borrow flag, so the flag wins when X is F
+    fn arithmetic<F>(&mut self, x: u16, y: u16, operation: F)
     where
-        F: Fn(i16, i16) -> i16,
+        F: Fn(u8, u8) -> (u8, bool),
     {
-        let vx = self.v_registers[source as usize] as i16;
-        let vy = self.v_registers[other as usize] as i16;
-        let result = operation(vx, vy);
+        let vx = self.v_registers[x as usize];
+        let vy = self.v_registers[y as usize];
+        let (result, flag) = operation(vx, vy);
+        self.v_registers[x as usize] = result;
+        self.v_registers[0xf] = flag as u8;
+    }
 
-        if (result < 0 || result > 255) && trigger {
-            self.v_registers[0xf] = !(self.v_registers[0xf] == 1) as u8;
-        }
+    fn read(&self, target: usize) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(target)
+            .copied()
+            .ok_or(Chip8Error::OutOfBounds {
+                target,
+                address: self.pc.saturating_sub(2),
+            })
+    }
 
-        self.v_registers[source as usize] = (result & 255i16) as u8;
+    fn write(&mut self, target: usize, value: u8) -> Result<(), Chip8Error> {
+        let address = self.pc.saturating_sub(2);
+        let byte = self
+            .memory
+            .get_mut(target)
+            .ok_or(Chip8Error::OutOfBounds { target, address })?;
+        *byte = value;
+        Ok(())
     }
 
-    fn fetch(&mut self) -> Instruction {
-        //fetch
-        let instruction: u16 =
-            ((self.memory[self.pc] as u16) << 8) | (self.memory[self.pc + 1] as u16);
+    fn fetch(&mut self) -> Result<Instruction, Chip8Error> {
+        let instruction = self.peek()?;
         self.pc += 2;
+        Ok(instruction)
+    }
 
-        //decode
-        let nibble_1 = ((0xf << 12) & instruction) >> 12;
-        let nibble_2 = ((0xf << 8) & instruction) >> 8;
-        let nibble_3 = ((0xf << 4) & instruction) >> 4;
-        let nibble_4 = 0xf & instruction;
-
-        Instruction(nibble_1, nibble_2, nibble_3, nibble_4)
+    // Decode instruction at PC without executing it
+    pub(crate) fn peek(&self) -> Result<Instruction, Chip8Error> {
+        //fetch
+        let out_of_bounds = |target| Chip8Error::OutOfBounds {
+            target,
+            address: self.pc,
+        };
+        let high = *self.memory.get(self.pc).ok_or(out_of_bounds(self.pc))?;
+        let low = *self
+            .memory
+            .get(self.pc + 1)
+            .ok_or(out_of_bounds(self.pc + 1))?;
+        Ok(Instruction::decode(((high as u16) << 8) | (low as u16)))
     }
 
-    fn execute(&mut self, instruction: &Instruction) {
+    fn execute(&mut self, instruction: &Instruction) -> Result<(), Chip8Error> {
+        let address = self.pc - 2;
         // Execute
         match *instruction {
-            Instruction(0, 0, 0xe, 0) => self.pixels = [[false; W_WIDTH]; W_HEIGHT],
-            Instruction(0, 0, 0xe, 0xe) => self.pc = self.stack.pop().unwrap(),
+            Instruction(0, 0, 0xe, 0) => self.clear(),
+            Instruction(0, 0, 0xc, n) => self.scroll(0, n as isize),
+            Instruction(0, 0, 0xd, n) => self.scroll(0, -(n as isize)),
+            Instruction(0, 0, 0xf, 0xb) => self.scroll(4, 0),
+            Instruction(0, 0, 0xf, 0xc) => self.scroll(-4, 0),
+            Instruction(0, 0, 0xf, 0xd) => self.exited = true,
+            Instruction(0, 0, 0xf, 0xe) => {
+                self.hires = false;
+                self.clear()
+            }
+            Instruction(0, 0, 0xf, 0xf) => {
+                self.hires = true;
+                self.clear()
+            }
+            Instruction(0, 0, 0xe, 0xe) => {
+                self.pc = self
+                    .stack
+                    .pop()
+                    .ok_or(Chip8Error::StackUnderflow { address })?
+            }
             Instruction(1, ..) => self.pc = usize::from(instruction),
             Instruction(2, ..) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow { address });
+                }
                 self.stack.push(self.pc);
                 self.pc = usize::from(instruction)
             }
             Instruction(3, x, ..) => {
                 if self.v_registers[x as usize] == u8::from(instruction) {
-                    self.pc += 2
+                    self.skip()?
                 }
             }
             Instruction(4, x, ..) => {
                 if self.v_registers[x as usize] != u8::from(instruction) {
-                    self.pc += 2
+                    self.skip()?
                 }
             }
-            Instruction(5, x, y, ..) => {
+            Instruction(5, x, y, 0) => {
                 if self.v_registers[x as usize] == self.v_registers[y as usize] {
-                    self.pc += 2
+                    self.skip()?
+                }
+            }
+            Instruction(5, x, y, 2) => {
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    self.write(
+                        self.i_register as usize + offset,
+                        self.v_registers[register],
+                    )?
+                }
+            }
+            Instruction(5, x, y, 3) => {
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    self.v_registers[register] = self.read(self.i_register as usize + offset)?
                 }
             }
             Instruction(6, x, ..) => self.v_registers[x as usize] = u8::from(instruction),
@@ -146,203 +272,963 @@ impl Chip8 {
                     & 255u16) as u8
             }
             Instruction(8, x, y, 0) => self.v_registers[x as usize] = self.v_registers[y as usize],
-            Instruction(8, x, y, 1) => self.compute(x, y, |u, v| u | v, false),
-            Instruction(8, x, y, 2) => self.compute(x, y, |u, v| u & v, false),
-            Instruction(8, x, y, 3) => self.compute(x, y, |u, v| u ^ v, false),
-            Instruction(8, x, y, 4) => {
-                self.v_registers[0xf] = 0;
-                self.compute(x, y, |u, v| u + v, true);
-            }
-            Instruction(8, x, y, 5) => {
-                self.v_registers[0xf] = 1;
-                self.compute(x, y, |u, v| u - v, true);
-            }
-            Instruction(8, x, .., 6) => {
-                self.v_registers[0xf] = self.v_registers[x as usize] & 1u8;
-                self.v_registers[x as usize] >>= 1;
-            }
-            Instruction(8, x, y, 7) => {
-                self.v_registers[0xf] = 1;
-                self.compute(x, y, |u, v| v - u, true);
-            }
-            Instruction(8, x, .., 0xe) => {
-                self.v_registers[0xf] = self.v_registers[x as usize] & 128u8;
-                self.v_registers[x as usize] <<= 1;
-            }
+            Instruction(8, x, y, 1) => self.logic(x, y, |u, v| u | v),
+            Instruction(8, x, y, 2) => self.logic(x, y, |u, v| u & v),
+            Instruction(8, x, y, 3) => self.logic(x, y, |u, v| u ^ v),
+            Instruction(8, x, y, 4) => self.arithmetic(x, y, |u, v| u.overflowing_add(v)),
+            Instruction(8, x, y, 5) => self.arithmetic(x, y, |u, v| {
+                let (result, borrow) = u.overflowing_sub(v);
+                (result, !borrow)
+            }),
+            Instruction(8, x, y, 6) => self.shift(x, y, |source| (source >> 1, source & 1)),
+            Instruction(8, x, y, 7) => self.arithmetic(x, y, |u, v| {
+                let (result, borrow) = v.overflowing_sub(u);
+                (result, !borrow)
+            }),
+            Instruction(8, x, y, 0xe) => self.shift(x, y, |source| (source << 1, source >> 7)),
             Instruction(9, x, y, ..) => {
                 if self.v_registers[x as usize] != self.v_registers[y as usize] {
-                    self.pc += 2
+                    self.skip()?
                 }
             }
             Instruction(0xa, ..) => self.i_register = u16::from(instruction),
-            Instruction(0xb, ..) => {
-                self.pc = (u16::from(instruction) + (self.v_registers[0] as u16)) as usize
+            Instruction(0xb, x, ..) => {
+                let offset = if self.quirks.jump_with_vx {
+                    self.v_registers[x as usize]
+                } else {
+                    self.v_registers[0]
+                };
+                self.pc = (u16::from(instruction) + (offset as u16)) as usize
             }
             Instruction(0xc, x, ..) => {
                 let mut rng = rand::thread_rng();
                 self.v_registers[x as usize] = rng.gen::<u8>() & u8::from(instruction);
             }
-            Instruction(0xd, x, y, n) => self.draw(x, y, n),
+            Instruction(0xd, x, y, n) => {
+                if self.quirks.display_wait && !self.vblank {
+                    // Retry until next frame
+                    self.pc -= 2;
+                } else {
+                    self.vblank = false;
+                    self.draw(x, y, n)?
+                }
+            }
             Instruction(0xe, x, 9, 0xe) => {
                 if let KeyState::Pressed = self.keys_states[x as usize] {
-                    self.pc += 2;
+                    self.skip()?
                 }
             }
             Instruction(0xe, x, 0xa, 1) => {
                 if let KeyState::Idle = self.keys_states[x as usize] {
-                    self.pc += 2;
+                    self.skip()?
+                }
+            }
+            Instruction(0xf, 0, 0, 0) => {
+                let high = self.read(self.pc)? as u16;
+                let low = self.read(self.pc + 1)? as u16;
+                self.i_register = (high << 8) | low;
+                self.pc += 2;
+            }
+            Instruction(0xf, n, 0, 1) => self.planes = n as u8 & 0b11,
+            Instruction(0xf, 0, 0, 2) => {
+                let mut pattern = [0u8; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read(self.i_register as usize + offset)?;
                 }
+                self.audio_pattern = Some(pattern);
             }
+            Instruction(0xf, x, 3, 0xa) => self.pitch = self.v_registers[x as usize],
             Instruction(0xf, x, 0, 7) => self.v_registers[x as usize] = self.delay_timer,
             Instruction(0xf, x, 1, 5) => self.delay_timer = self.v_registers[x as usize],
             Instruction(0xf, x, 1, 8) => self.sound_timer = self.v_registers[x as usize],
             Instruction(0xf, x, 1, 0xe) => {
-                let result = self.i_register + (self.v_registers[x as usize] as u16);
-                self.v_registers[0xf] = (result > 0x0fff) as u8;
-                self.i_register = result & 0xfff;
+                // VF is left alone: I above 0xFFF is ordinary XO-CHIP memory
+                self.i_register = self
+                    .i_register
+                    .wrapping_add(self.v_registers[x as usize] as u16);
             }
             Instruction(0xf, x, 0, 0xa) => {
-                if let KeyState::Idle = self.keys_states[x as usize] {
-                    self.pc -= 2
+                // Block by executing FX0A again, timers keep running meanwhile
+                match self.wait_key() {
+                    Some(key) => self.v_registers[x as usize] = key,
+                    None => self.pc -= 2,
                 }
-            } // Freeze until key pressed
+            }
             Instruction(0xf, x, 2, 9) => {
                 self.i_register = (FONT_OFFSET as u16) + 5 * (self.v_registers[x as usize] as u16)
             }
+            Instruction(0xf, x, 3, 0) => {
+                self.i_register =
+                    (BIG_FONT_OFFSET as u16) + 10 * ((self.v_registers[x as usize] & 0xf) as u16)
+            }
+            Instruction(0xf, x, 7, 5) => {
+                self.rpl_flags[..=x as usize].copy_from_slice(&self.v_registers[..=x as usize])
+            }
+            Instruction(0xf, x, 8, 5) => {
+                self.v_registers[..=x as usize].copy_from_slice(&self.rpl_flags[..=x as usize])
+            }
             Instruction(0xf, x, 3, 3) => {
                 let vx: u16 = self.v_registers[x as usize] as u16;
                 for i in 0..3u32 {
-                    self.memory[(self.i_register + (i as u16)) as usize] =
-                        (((vx % 10u16.pow(3 - i)) / 10u16.pow(2 - i)) & 255u16) as u8;
+                    self.write(
+                        (self.i_register as usize) + (i as usize),
+                        (((vx % 10u16.pow(3 - i)) / 10u16.pow(2 - i)) & 255u16) as u8,
+                    )?;
                 }
             }
             Instruction(0xf, x, 5, 5) => {
-                for i in 0..=x {
-                    self.memory[(self.i_register + i) as usize] = self.v_registers[i as usize]
+                for i in 0..=x as usize {
+                    self.write(self.i_register as usize + i, self.v_registers[i])?
                 }
+                self.increment_i(x);
             }
             Instruction(0xf, x, 6, 5) => {
-                for i in 0..=x {
-                    self.v_registers[i as usize] = self.memory[(self.i_register + i) as usize]
+                for i in 0..=x as usize {
+                    self.v_registers[i] = self.read(self.i_register as usize + i)?
+                }
+                self.increment_i(x);
+            }
+            _ => {
+                return Err(Chip8Error::UnknownOpcode {
+                    opcode: instruction.opcode(),
+                    address,
+                })
+            }
+        };
+        Ok(())
+    }
+
+    // Bitwise operations, resetting VF on the COSMAC VIP
+    fn logic<F>(&mut self, x: u16, y: u16, operation: F)
+    where
+        F: Fn(u8, u8) -> u8,
+    {
+        self.v_registers[x as usize] =
+            operation(self.v_registers[x as usize], self.v_registers[y as usize]);
+        if self.quirks.vf_reset {
+            self.v_registers[0xf] = 0;
+        }
+    }
+
+    // 8XY6 and 8XYE: `operation` returns the shifted value and the bit shifted
+    // out, written to VF last
+    fn shift<F>(&mut self, x: u16, y: u16, operation: F)
+    where
+        F: Fn(u8) -> (u8, u8),
+    {
+        let source = if self.quirks.shift_uses_vy {
+            self.v_registers[y as usize]
+        } else {
+            self.v_registers[x as usize]
+        };
+        let (result, flag) = operation(source);
+        self.v_registers[x as usize] = result;
+        self.v_registers[0xf] = flag;
+    }
+
+    // Key to store for FX0A, if any. Any key pressed after blocking is taken,
+    // then with the release quirk that same key has to be released.
+    fn wait_key(&mut self) -> Option<u8> {
+        let down = self
+            .keys_states
+            .map(|state| matches!(state, KeyState::Pressed));
+        let held = self.held_keys.get_or_insert(down);
+        for (held, down) in held.iter_mut().zip(down) {
+            *held &= down;
+        }
+        let pressed = (0..16)
+            .find(|&key| down[key] && !held[key])
+            .map(|key| key as u8);
+        let key = if !self.quirks.key_release {
+            pressed
+        } else {
+            match self.waiting_key {
+                Some(key) if matches!(self.keys_states[key as usize], KeyState::Idle) => {
+                    self.waiting_key = None;
+                    Some(key)
+                }
+                Some(_) => None,
+                None => {
+                    self.waiting_key = pressed;
+                    None
                 }
             }
-            _ => panic!("Unknow instruction {:?}", instruction),
         };
+        if key.is_some() {
+            self.held_keys = None;
+        }
+        key
+    }
+
+    // Move I after FX55/FX65
+    fn increment_i(&mut self, x: u16) {
+        match self.quirks.load_store {
+            LoadStore::Increment => self.i_register = self.i_register.wrapping_add(x + 1),
+            LoadStore::IncrementX => self.i_register = self.i_register.wrapping_add(x),
+            LoadStore::Unchanged => (),
+        }
+    }
+
+    // Skip the next instruction, including the second half of a long F000 NNNN
+    fn skip(&mut self) -> Result<(), Chip8Error> {
+        let long = self.read(self.pc)? == 0xf0 && self.read(self.pc + 1)? == 0x00;
+        self.pc += if long { 4 } else { 2 };
+        Ok(())
+    }
+
+    // Registers from VX to VY for 5XY2/5XY3, in descending order when X > Y
+    fn register_range(x: u16, y: u16) -> Box<dyn Iterator<Item = usize>> {
+        let (x, y) = (x as usize, y as usize);
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
     }
 
-    fn draw(&mut self, x: u16, y: u16, n: u16) {
+    fn clear(&mut self) {
+        let planes = self.planes;
+        self.pixels
+            .iter_mut()
+            .flatten()
+            .for_each(|pixel| *pixel &= !planes);
+    }
+
+    // Move the selected planes by (dx, dy) pixels, filling with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width(), self.height());
+        let previous = self.pixels;
+        for y in 0..height {
+            for x in 0..width {
+                let (s_x, s_y) = (x as isize - dx, y as isize - dy);
+                let source =
+                    if (0..width as isize).contains(&s_x) && (0..height as isize).contains(&s_y) {
+                        previous[s_y as usize][s_x as usize]
+                    } else {
+                        0
+                    };
+                self.pixels[y][x] = (self.pixels[y][x] & !self.planes) | (source & self.planes);
+            }
+        }
+    }
+
+    // Draw an 8xN sprite, or a 16x16 one when N is 0, on each selected plane.
+    // Sprite data for the planes follows each other in memory.
+    fn draw(&mut self, x: u16, y: u16, n: u16) -> Result<(), Chip8Error> {
+        let (width, height) = (self.width(), self.height());
         // Modulo coordinates to stay in range
-        let x = (self.v_registers[x as usize] & 63) as usize;
-        let y = (self.v_registers[y as usize] & 31) as usize;
-        let i = self.i_register;
+        let x = self.v_registers[x as usize] as usize % width;
+        let y = self.v_registers[y as usize] as usize % height;
+        let mut i = self.i_register as usize;
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+        let row_bytes = cols / 8;
 
         self.v_registers[0xf] = 0;
 
-        for row in 0..usize::from(n) {
-            let sprite = self.memory[usize::from(i) + row];
-            for col in 0..8 {
-                let (c_x, c_y) = (x + col, y + row);
-                if (c_y < W_HEIGHT) && (c_x < W_WIDTH) {
-                    let d_pixel = self.pixels[c_y][c_x];
-                    let s_pixel = (((0x80 >> col) & sprite) >> (7 - col)) != 0;
-                    if d_pixel && s_pixel {
-                        self.v_registers[0xf] = 1;
+        for plane in [1u8, 2].into_iter().filter(|p| self.planes & p != 0) {
+            for row in 0..rows {
+                let mut sprite = 0u16;
+                for byte in 0..row_bytes {
+                    sprite = (sprite << 8) | self.read(i + row * row_bytes + byte)? as u16;
+                }
+                for col in 0..cols {
+                    let (mut c_x, mut c_y) = (x + col, y + row);
+                    if !self.quirks.clip_sprites {
+                        (c_x, c_y) = (c_x % width, c_y % height);
+                    }
+                    if (c_y < height) && (c_x < width) && (sprite >> (cols - 1 - col)) & 1 != 0 {
+                        if self.pixels[c_y][c_x] & plane != 0 {
+                            self.v_registers[0xf] = 1;
+                        }
+                        self.pixels[c_y][c_x] ^= plane;
                     }
-                    self.pixels[c_y][c_x] = d_pixel ^ s_pixel;
                 }
             }
+            i += rows * row_bytes;
         }
+        Ok(())
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new(Quirks::default())
     }
 }
 
 // public method
 impl Chip8 {
-    pub fn new() -> Self {
-        let mut memory = [0; 4096];
-        // Fill font in memory
-        for (pos, &b) in FONT.iter().enumerate() {
-            memory[FONT_OFFSET + pos] = b;
-        }
+    pub fn new(quirks: Quirks) -> Self {
+        let mut memory = [0; MEMORY_SIZE];
+        // Fill fonts in memory
+        memory[FONT_OFFSET..FONT_OFFSET + FONT.len()].copy_from_slice(&FONT);
+        memory[BIG_FONT_OFFSET..BIG_FONT_OFFSET + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         Self {
             memory,
-            pixels: [[false; W_WIDTH]; W_HEIGHT],
+            pixels: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+            planes: 1,
+            hires: false,
             pc: LOAD_START,
             i_register: 0u16,
             v_registers: [0u8; 16],
             stack: vec![],
             delay_timer: 0u8,
             sound_timer: 0u8,
-            keys: DEFAULT_KEYS.to_string(),
             keys_states: [KeyState::Idle; 16],
+            waiting_key: None,
+            held_keys: None,
+            rpl_flags: [0u8; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            exited: false,
+            quirks,
+            vblank: false,
         }
     }
 
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Display content in the current resolution, lit on any plane
     pub fn pixels(&self) -> Vec<Vec<bool>> {
-        self.pixels
-            .into_iter()
-            .map(|row| row.into_iter().collect())
+        self.pixels[..self.height()]
+            .iter()
+            .map(|row| row[..self.width()].iter().map(|&p| p != 0).collect())
             .collect()
     }
-    pub fn load(&mut self, instructions: &[u8]) {
-        // Fill with chip 8 instrucitons
-        for (pos, &b) in instructions.iter().enumerate() {
-            self.memory[LOAD_START + pos] = b;
+
+    // Display content as palette indices, bit N set when lit on plane N
+    pub fn planes(&self) -> Vec<Vec<u8>> {
+        self.pixels[..self.height()]
+            .iter()
+            .map(|row| row[..self.width()].to_vec())
+            .collect()
+    }
+
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            W_WIDTH
         }
     }
 
-    pub fn update_key_states(&mut self, key: &str, state: ElementState) {
-        if self.keys.contains(key) {
-            let key_idx: usize = self.keys.find(key).unwrap();
-            match state {
-                ElementState::Pressed => self.keys_states[key_idx] = KeyState::Pressed,
-                ElementState::Released => self.keys_states[key_idx] = KeyState::Idle,
-            }
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            W_HEIGHT
         }
     }
 
-    pub fn step(&mut self) -> Target {
-        let start = Instant::now();
-        let instruction = self.fetch();
-        let _ = self.execute(&instruction);
-
-        // Expected time wait depending on the instruction
-        let mut rng = thread_rng();
-        let interval_dist = match instruction {
-            Instruction(0, 0, 0xe, 0) => Normal::new(109.0, 0.0).unwrap(),
-            Instruction(0, 0, 0xe, 0xe)
-            | Instruction(1, ..)
-            | Instruction(2, ..)
-            | Instruction(0xb, ..) => Normal::new(105.0, 5.0).unwrap(),
-            Instruction(3, ..) | Instruction(4, ..) | Instruction(0xa, ..) => {
-                Normal::new(55.0, 9.0).unwrap()
-            }
-            Instruction(5, ..) | Instruction(9, ..) | Instruction(0xe, ..) => {
-                Normal::new(73.0, 0.0).unwrap()
-            }
-            Instruction(6, ..) => Normal::new(27.0, 0.0).unwrap(),
-            Instruction(7, ..)
-            | Instruction(0xf, .., 0, 7)
-            | Instruction(0xf, .., 1, 5)
-            | Instruction(0xf, .., 1, 8) => Normal::new(45.0, 0.0).unwrap(),
-            Instruction(8, ..) => Normal::new(200.0, 0.0).unwrap(),
-            Instruction(0xc, ..) => Normal::new(164.0, 0.0).unwrap(),
-            Instruction(0xf, .., 0, 0xa) => Normal::new(0.0, 0.0).unwrap(),
-            Instruction(0xf, .., 1, 0xe) => Normal::new(86.0, 14.0).unwrap(),
-            Instruction(0xf, .., 2, 9) => Normal::new(91.0, 0.0).unwrap(),
-            Instruction(0xf, .., 3, 3) => Normal::new(927.0, 545.0).unwrap(),
-            Instruction(0xf, .., 5 | 6, 5) => Normal::new(605.0, 477.0).unwrap(),
-            Instruction(0xd, ..) => Normal::new(22734.0, 4634.0).unwrap(),
-            _ => panic!("Unknow Instruction {:?}", instruction),
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Set a memory byte from outside of a program, like the frontend or tests
+    pub fn write_memory(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        self.write(address, value)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn v_registers(&self) -> [u8; 16] {
+        self.v_registers
+    }
+
+    // Return addresses of the subroutines being executed
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Count down both timers, to be called at 60 Hz on vertical blank
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    pub fn load(&mut self, instructions: &[u8]) -> Result<(), Chip8Error> {
+        let max = MEMORY_SIZE - LOAD_START;
+        if instructions.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: instructions.len(),
+                max,
+            });
+        }
+        // Fill with chip 8 instrucitons
+        self.memory[LOAD_START..LOAD_START + instructions.len()].copy_from_slice(instructions);
+        Ok(())
+    }
+
+    // Press or release keypad key 0 to F
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keys_states[key as usize & 0xf] = if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Idle
         };
+    }
 
-        //let interval = Duration::from_micros(interval_dist.sample(&mut rng) as u64);
-        //while start.elapsed() < interval {
-        //    spin_loop()
-        //}
+    // Serialize the whole machine state, except quirks which come from the
+    // frontend configuration
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.bytes(&self.memory);
+        for row in &self.pixels {
+            writer.bytes(row);
+        }
+        writer.u8(self.planes);
+        writer.bool(self.hires);
+        writer.u32(self.pc as u32);
+        writer.u16(self.i_register);
+        writer.bytes(&self.v_registers);
+        writer.u8(self.stack.len() as u8);
+        for &address in &self.stack {
+            writer.u32(address as u32);
+        }
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        for state in self.keys_states {
+            writer.bool(matches!(state, KeyState::Pressed));
+        }
+        writer.bool(self.waiting_key.is_some());
+        writer.u8(self.waiting_key.unwrap_or_default());
+        writer.bool(self.held_keys.is_some());
+        for held in self.held_keys.unwrap_or_default() {
+            writer.bool(held);
+        }
+        writer.bytes(&self.rpl_flags);
+        writer.bool(self.audio_pattern.is_some());
+        writer.bytes(&self.audio_pattern.unwrap_or_default());
+        writer.u8(self.pitch);
+        writer.bool(self.exited);
+        writer.bool(self.vblank);
+        writer.finish()
+    }
+
+    // Restore a state produced by `snapshot`, leaving the machine untouched on error
+    pub fn restore(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = StateReader::new(state)?;
+        let mut chip = Chip8::new(self.quirks);
 
-        match instruction {
-            Instruction(0xd, ..) | Instruction(0, 0, 0xe, 0) => Target::Pixels,
+        chip.memory.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        for row in chip.pixels.iter_mut() {
+            row.copy_from_slice(reader.bytes(HIRES_WIDTH)?);
+        }
+        chip.planes = reader.u8()?;
+        chip.hires = reader.bool()?;
+        chip.pc = reader.u32()? as usize;
+        chip.i_register = reader.u16()?;
+        chip.v_registers = reader.array()?;
+        let depth = reader.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(Chip8Error::InvalidState {
+                reason: format!("stack depth {} exceeds {}", depth, STACK_SIZE),
+            });
+        }
+        for _ in 0..depth {
+            chip.stack.push(reader.u32()? as usize);
+        }
+        chip.delay_timer = reader.u8()?;
+        chip.sound_timer = reader.u8()?;
+        for state in chip.keys_states.iter_mut() {
+            if reader.bool()? {
+                *state = KeyState::Pressed;
+            }
+        }
+        let waiting = reader.bool()?;
+        let key = reader.u8()?;
+        chip.waiting_key = waiting.then_some(key & 0xf);
+        let blocked = reader.bool()?;
+        let mut held_keys = [false; 16];
+        for held in held_keys.iter_mut() {
+            *held = reader.bool()?;
+        }
+        chip.held_keys = blocked.then_some(held_keys);
+        chip.rpl_flags = reader.array()?;
+        let has_pattern = reader.bool()?;
+        let pattern = reader.array()?;
+        chip.audio_pattern = has_pattern.then_some(pattern);
+        chip.pitch = reader.u8()?;
+        chip.exited = reader.bool()?;
+        chip.vblank = reader.bool()?;
+        reader.finish()?;
+
+        *self = chip;
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<Target, Chip8Error> {
+        if self.exited {
+            return Ok(Target::Exit);
+        }
+        let instruction = self.fetch()?;
+        log::debug!("{:03X}: {:?}", self.pc - 2, instruction);
+        self.execute(&instruction)?;
+
+        Ok(match instruction {
+            Instruction(0, 0, 0xf, 0xd) => Target::Exit,
+            Instruction(0xd, ..)
+            | Instruction(0, 0, 0xe, 0)
+            | Instruction(0, 0, 0xc | 0xd, _)
+            | Instruction(0, 0, 0xf, 0xb..=0xf) => Target::Pixels,
             _ => Target::Memory,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rom: &[u8], steps: usize) -> Result<Chip8, Chip8Error> {
+        run_with(Quirks::default(), rom, steps)
+    }
+
+    fn run_with(quirks: Quirks, rom: &[u8], steps: usize) -> Result<Chip8, Chip8Error> {
+        let mut chip = Chip8::new(quirks);
+        chip.load(rom)?;
+        for _ in 0..steps {
+            chip.step()?;
         }
+        Ok(chip)
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        assert_eq!(
+            run(&[0x60, 0x01, 0xff, 0xff], 2).unwrap_err(),
+            Chip8Error::UnknownOpcode {
+                opcode: 0xffff,
+                address: 0x202
+            }
+        );
+    }
+
+    #[test]
+    fn stack_errors() {
+        assert_eq!(
+            run(&[0x00, 0xee], 1).unwrap_err(),
+            Chip8Error::StackUnderflow { address: 0x200 }
+        );
+        // Call itself forever
+        assert_eq!(
+            run(&[0x22, 0x00], STACK_SIZE + 1).unwrap_err(),
+            Chip8Error::StackOverflow { address: 0x200 }
+        );
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        // I := long FFFF; save V0 - V1
+        assert_eq!(
+            run(&[0xf0, 0x00, 0xff, 0xff, 0xf1, 0x55], 2).unwrap_err(),
+            Chip8Error::OutOfBounds {
+                target: 0x10000,
+                address: 0x204
+            }
+        );
+    }
+
+    #[test]
+    fn rom_too_large() {
+        assert_eq!(
+            Chip8::default().load(&[0; MEMORY_SIZE]),
+            Err(Chip8Error::RomTooLarge {
+                size: MEMORY_SIZE,
+                max: MEMORY_SIZE - LOAD_START
+            })
+        );
+    }
+
+    // V0 := a; V1 := b; then `opcode`, returning V0 and VF
+    fn alu(opcode: u16, a: u8, b: u8) -> (u8, u8) {
+        let [high, low] = opcode.to_be_bytes();
+        let chip = run(&[0x60, a, 0x61, b, high, low], 3).unwrap();
+        (chip.v_registers[0], chip.v_registers[0xf])
+    }
+
+    #[test]
+    fn alu_load_and_logic() {
+        assert_eq!(alu(0x8010, 1, 2), (2, 0));
+        assert_eq!(alu(0x8011, 0b1100, 0b1010), (0b1110, 0));
+        assert_eq!(alu(0x8012, 0b1100, 0b1010), (0b1000, 0));
+        assert_eq!(alu(0x8013, 0b1100, 0b1010), (0b0110, 0));
+    }
+
+    #[test]
+    fn alu_add() {
+        assert_eq!(alu(0x8014, 1, 2), (3, 0));
+        assert_eq!(alu(0x8014, 200, 100), (44, 1));
+        assert_eq!(alu(0x8014, 255, 1), (0, 1));
+        // VF := 200; V1 := 100; VF += V1, the carry replaces the sum
+        let chip = run(&[0x6f, 200, 0x61, 100, 0x8f, 0x14], 3).unwrap();
+        assert_eq!(chip.v_registers[0xf], 1);
+        // V0 := 5; VF := 3; V0 += VF
+        let chip = run(&[0x60, 5, 0x6f, 3, 0x80, 0xf4], 3).unwrap();
+        assert_eq!((chip.v_registers[0], chip.v_registers[0xf]), (8, 0));
+    }
+
+    #[test]
+    fn alu_sub() {
+        assert_eq!(alu(0x8015, 5, 3), (2, 1));
+        assert_eq!(alu(0x8015, 5, 5), (0, 1));
+        assert_eq!(alu(0x8015, 3, 5), (254, 0));
+        // VF := 3; V1 := 5; VF -= V1
+        let chip = run(&[0x6f, 3, 0x61, 5, 0x8f, 0x15], 3).unwrap();
+        assert_eq!(chip.v_registers[0xf], 0);
+        // V0 := 7; VF := 2; V0 -= VF
+        let chip = run(&[0x60, 7, 0x6f, 2, 0x80, 0xf5], 3).unwrap();
+        assert_eq!((chip.v_registers[0], chip.v_registers[0xf]), (5, 1));
+    }
+
+    #[test]
+    fn alu_subn() {
+        assert_eq!(alu(0x8017, 3, 5), (2, 1));
+        assert_eq!(alu(0x8017, 5, 5), (0, 1));
+        assert_eq!(alu(0x8017, 5, 3), (254, 0));
+        // VF := 6; V1 := 5; VF =- V1
+        let chip = run(&[0x6f, 6, 0x61, 5, 0x8f, 0x17], 3).unwrap();
+        assert_eq!(chip.v_registers[0xf], 0);
+    }
+
+    #[test]
+    fn alu_shifts() {
+        // Same value in V0 and V1, whichever the quirk shifts
+        assert_eq!(alu(0x8016, 0x81, 0x81), (0x40, 1));
+        assert_eq!(alu(0x8016, 0x80, 0x80), (0x40, 0));
+        assert_eq!(alu(0x801e, 0x81, 0x81), (0x02, 1));
+        assert_eq!(alu(0x801e, 0x41, 0x41), (0x82, 0));
+        // VF := 3; VF >>= VF, then VF := 2; VF <<= VF
+        let chip = run(&[0x6f, 3, 0x8f, 0xf6], 2).unwrap();
+        assert_eq!(chip.v_registers[0xf], 1);
+        let chip = run(&[0x6f, 2, 0x8f, 0xfe], 2).unwrap();
+        assert_eq!(chip.v_registers[0xf], 0);
+    }
+
+    #[test]
+    fn quirk_shift_source() {
+        // V0 := 1; V1 := 6; V0 >>= V1
+        let rom = [0x60, 0x01, 0x61, 0x06, 0x80, 0x16];
+        assert_eq!(
+            run_with(Quirks::cosmac_vip(), &rom, 3).unwrap().v_registers[0],
+            3
+        );
+        assert_eq!(
+            run_with(Quirks::chip48(), &rom, 3).unwrap().v_registers[0],
+            0
+        );
+    }
+
+    #[test]
+    fn quirk_jump_with_offset() {
+        // V0 := 2; V3 := 4; jump0 300
+        let rom = [0x60, 0x02, 0x63, 0x04, 0xb3, 0x00];
+        assert_eq!(run_with(Quirks::cosmac_vip(), &rom, 3).unwrap().pc, 0x302);
+        assert_eq!(run_with(Quirks::chip48(), &rom, 3).unwrap().pc, 0x304);
+    }
+
+    #[test]
+    fn quirk_load_store() {
+        // I := 300; save V0 - V2
+        let rom = [0xa3, 0x00, 0xf2, 0x55];
+        assert_eq!(
+            run_with(Quirks::cosmac_vip(), &rom, 2).unwrap().i_register,
+            0x303
+        );
+        assert_eq!(
+            run_with(Quirks::chip48(), &rom, 2).unwrap().i_register,
+            0x302
+        );
+        assert_eq!(
+            run_with(Quirks::superchip(), &rom, 2).unwrap().i_register,
+            0x300
+        );
+    }
+
+    #[test]
+    fn quirk_vf_reset() {
+        // VF := 1; V0 |= V1
+        let rom = [0x6f, 0x01, 0x80, 0x11];
+        assert_eq!(
+            run_with(Quirks::cosmac_vip(), &rom, 2).unwrap().v_registers[0xf],
+            0
+        );
+        assert_eq!(
+            run_with(Quirks::modern(), &rom, 2).unwrap().v_registers[0xf],
+            1
+        );
+    }
+
+    #[test]
+    fn quirk_display_wait() {
+        // sprite V0 V0 1 twice
+        let rom = [0xd0, 0x01, 0xd0, 0x01];
+        let mut chip = Chip8::new(Quirks::cosmac_vip());
+        chip.load(&rom).unwrap();
+        chip.tick_timers();
+        chip.step().unwrap();
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x202);
+        chip.tick_timers();
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn quirk_clip_sprites() {
+        // V0 := 3C; I := font 0; sprite V0 V0 5, drawn at (60, 60 % 32)
+        let rom = [0x60, 0x3c, 0xa0, 0x50, 0xd0, 0x05];
+        let clipped = run_with(Quirks::chip48(), &rom, 3).unwrap();
+        assert!(clipped.pixels()[28][60]);
+        assert!(!clipped.pixels()[28][0]);
+        assert!(!clipped.pixels()[0][60]);
+        let wrapped = run_with(Quirks::modern(), &rom, 3).unwrap();
+        assert!(wrapped.pixels()[28][60]);
+        assert!(!wrapped.pixels()[28][0]);
+        assert!(wrapped.pixels()[0][60]);
+        // Font 0 is 4 pixels wide, draw at column 62 to wrap horizontally
+        let rom = [0x60, 0x3e, 0x61, 0x00, 0xa0, 0x50, 0xd0, 0x15];
+        let wrapped = run_with(Quirks::modern(), &rom, 4).unwrap();
+        assert!(wrapped.pixels()[0][62]);
+        assert!(wrapped.pixels()[0][1]);
+    }
+
+    #[test]
+    fn wait_key() {
+        // V0 := 10; delay := V0; V3 := key
+        let rom = [0x60, 0x0a, 0xf0, 0x15, 0xf3, 0x0a];
+        let mut chip = run_with(Quirks::modern(), &rom, 3).unwrap();
+        assert_eq!(chip.pc, 0x204);
+        chip.tick_timers();
+        assert_eq!(chip.delay_timer, 9);
+        // Keypad key 5 is held, another key comes and goes meanwhile
+        chip.set_key(5, true);
+        chip.step().unwrap();
+        chip.set_key(1, true);
+        chip.step().unwrap();
+        chip.set_key(1, false);
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x204);
+        chip.set_key(5, false);
+        chip.step().unwrap();
+        assert_eq!((chip.pc, chip.v_registers[3]), (0x206, 5));
+
+        // CHIP-48 takes the key as soon as it is pressed
+        let mut chip = run_with(Quirks::chip48(), &rom, 3).unwrap();
+        chip.set_key(0xf, true);
+        chip.step().unwrap();
+        assert_eq!((chip.pc, chip.v_registers[3]), (0x206, 0xf));
+    }
+
+    #[test]
+    fn wait_key_ignores_held_keys() {
+        // V3 := key; jump to itself
+        let rom = [0xf3, 0x0a, 0x12, 0x02];
+        for quirks in [Quirks::modern(), Quirks::chip48()] {
+            let mut chip = Chip8::new(quirks);
+            chip.load(&rom).unwrap();
+            // Keypad key 5 is held before FX0A and released while blocked
+            chip.set_key(5, true);
+            chip.step().unwrap();
+            chip.set_key(5, false);
+            chip.step().unwrap();
+            assert_eq!(chip.pc, 0x200);
+            // Pressed again, it is taken
+            chip.set_key(5, true);
+            chip.step().unwrap();
+            chip.set_key(5, false);
+            chip.step().unwrap();
+            assert_eq!((chip.pc, chip.v_registers[3]), (0x202, 5));
+        }
+    }
+
+    #[test]
+    fn add_to_i_keeps_vf() {
+        // VF := 0x55; I := 0xFFF; V0 := 0x10; I += V0
+        let chip = run(&[0x6f, 0x55, 0xaf, 0xff, 0x60, 0x10, 0xf0, 0x1e], 4).unwrap();
+        assert_eq!((chip.i_register, chip.v_registers[0xf]), (0x100f, 0x55));
+    }
+
+    #[test]
+    fn schip_resolution() {
+        // hires; V0 := 7F; sprite V0 V0 1 (font 0 top row at 127, 63)
+        let chip = run(&[0x00, 0xff, 0x60, 0x7f, 0xa0, 0x50, 0xd0, 0x01], 4).unwrap();
+        assert!(chip.hires());
+        assert_eq!((chip.width(), chip.height()), (HIRES_WIDTH, HIRES_HEIGHT));
+        assert_eq!(chip.pixels().len(), HIRES_HEIGHT);
+        assert!(chip.pixels()[63][127]);
+        // lores clears the display
+        let chip = run(&[0x00, 0xff, 0xd0, 0x01, 0x00, 0xfe], 3).unwrap();
+        assert_eq!(chip.pixels().len(), W_HEIGHT);
+        assert!(chip.pixels().iter().flatten().all(|&p| !p));
+    }
+
+    #[test]
+    fn schip_scroll() {
+        // Draw font 0 top row (4 pixels) at (0, 0) then scroll
+        let draw = [0xa0, 0x50, 0xd0, 0x01];
+        let chip = run(&[&draw[..], &[0x00, 0xc2]].concat(), 3).unwrap();
+        assert!(!chip.pixels()[0][0] && chip.pixels()[2][0]);
+        let chip = run(&[&draw[..], &[0x00, 0xfb]].concat(), 3).unwrap();
+        assert!(!chip.pixels()[0][3] && chip.pixels()[0][4] && chip.pixels()[0][7]);
+        let chip = run(
+            &[&draw[..], &[0x00, 0xfb, 0x00, 0xfc, 0x00, 0xfc]].concat(),
+            5,
+        )
+        .unwrap();
+        assert!(chip.pixels().iter().flatten().all(|&p| !p));
+    }
+
+    #[test]
+    fn schip_large_sprite() {
+        // I := 300 (32 bytes of FF); sprite V0 V0 0
+        let mut rom = vec![0xa3, 0x00, 0xd0, 0x00];
+        rom.resize(0x100, 0);
+        rom.extend([0xff; 32]);
+        let chip = run(&rom, 2).unwrap();
+        let lit = chip.pixels().iter().flatten().filter(|&&p| p).count();
+        assert_eq!(lit, 256);
+        assert!(chip.pixels()[15][15] && !chip.pixels()[16][16]);
+    }
+
+    #[test]
+    fn schip_big_font_and_flags() {
+        // V0 := 2; I := big 2; V1 := 9; flags := V0 - V1; V0 := 0; V1 := 0; V0 - V1 := flags
+        let rom = [
+            0x60, 0x02, 0xf0, 0x30, 0x61, 0x09, 0xf1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xf1, 0x85,
+        ];
+        let chip = run(&rom, 7).unwrap();
+        assert_eq!(chip.i_register as usize, BIG_FONT_OFFSET + 20);
+        assert_eq!(chip.v_registers[..2], [2, 9]);
+    }
+
+    #[test]
+    fn schip_exit() {
+        let mut chip = run(&[0x00, 0xfd, 0x60, 0x01], 0).unwrap();
+        assert_eq!(chip.step(), Ok(Target::Exit));
+        assert_eq!(chip.step(), Ok(Target::Exit));
+        assert!(chip.exited());
+        assert_eq!(chip.v_registers[0], 0);
+    }
+
+    #[test]
+    fn xochip_long_load_and_skip() {
+        // I := long 1234; V0 := 0; if V0 == 0 skip over the next long load
+        let rom = [
+            0xf0, 0x00, 0x12, 0x34, 0x60, 0x00, 0x30, 0x00, 0xf0, 0x00, 0xab, 0xcd, 0x61, 0x01,
+        ];
+        let chip = run(&rom, 4).unwrap();
+        assert_eq!(chip.i_register, 0x1234);
+        assert_eq!(chip.v_registers[1], 1);
+    }
+
+    #[test]
+    fn xochip_register_ranges() {
+        // V1 := 1; V2 := 2; V3 := 3; I := 300; save V3 - V1; load V1 - V3 back
+        let rom = [
+            0x61, 0x01, 0x62, 0x02, 0x63, 0x03, 0xa3, 0x00, 0x53, 0x12, 0x51, 0x33,
+        ];
+        let chip = run(&rom, 5).unwrap();
+        assert_eq!(chip.memory[0x300..0x303], [3, 2, 1]);
+        assert_eq!(chip.i_register, 0x300);
+        let chip = run(&rom, 6).unwrap();
+        assert_eq!(chip.v_registers[1..4], [3, 2, 1]);
+    }
+
+    #[test]
+    fn xochip_planes() {
+        // plane 3; I := 300; sprite V0 V0 1 (row 80 on plane 1, row C0 on plane 2)
+        let mut rom = vec![0xf3, 0x01, 0xa3, 0x00, 0xd0, 0x01];
+        rom.resize(0x100, 0);
+        rom.extend([0x80, 0xc0]);
+        let mut chip = run(&rom, 3).unwrap();
+        assert_eq!(chip.planes()[0][..3], [3, 2, 0]);
+        // plane 2; clear only clears the second plane
+        chip.load(&[0xf2, 0x01, 0x00, 0xe0]).unwrap();
+        chip.pc = LOAD_START;
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.planes()[0][..3], [1, 0, 0]);
+        assert_eq!(chip.pixels()[0][..2], [true, false]);
+    }
+
+    #[test]
+    fn xochip_audio() {
+        // I := 300; audio; V0 := 70; pitch := V0
+        let mut rom = vec![0xa3, 0x00, 0xf0, 0x02, 0x60, 0x70, 0xf0, 0x3a];
+        rom.resize(0x100, 0);
+        rom.extend(0u8..16);
+        let chip = run(&rom, 4).unwrap();
+        assert_eq!(
+            chip.audio_pattern(),
+            Some(core::array::from_fn(|i| i as u8))
+        );
+        assert_eq!(chip.pitch(), 0x70);
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        // V0 := 7; call 208; I := 300; plane 2; sprite V0 V0 1; return
+        let rom = [
+            0x60, 0x07, 0x22, 0x08, 0x00, 0x00, 0x00, 0x00, 0xa3, 0x00, 0xf2, 0x01, 0xd0, 0x01,
+            0x00, 0xee,
+        ];
+        let mut chip = run(&rom, 5).unwrap();
+        chip.set_key(5, true);
+        let state = chip.snapshot();
+
+        let mut restored = Chip8::default();
+        restored.restore(&state).unwrap();
+        assert_eq!(restored.snapshot(), state);
+        assert_eq!(restored.planes(), chip.planes());
+        assert_eq!(restored.stack, [0x204]);
+        chip.step().unwrap();
+        restored.step().unwrap();
+        assert_eq!(restored.pc, 0x204);
+        assert_eq!(restored.snapshot(), chip.snapshot());
+    }
+
+    #[test]
+    fn restore_errors() {
+        let mut chip = run(&[0x60, 0x07], 1).unwrap();
+        let state = chip.snapshot();
+        assert!(chip.restore(&state[..state.len() - 1]).is_err());
+        assert!(chip.restore(&[state.as_slice(), &[0]].concat()).is_err());
+        // Unchanged after failures
+        assert_eq!(chip.v_registers[0], 7);
     }
 }
@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+
+use crate::options::Options;
+
+pub const USAGE: &str = "\
+Usage: crab8 [OPTIONS] <ROM>
+       crab8 disasm <ROM>
+       crab8 asm <SOURCE> [-o <ROM>]
+
+Commands:
+  disasm                   Print the ROM as assembly, separating code from data
+  asm                      Assemble a source file into a ROM, written next to the
+                           source with a .ch8 extension unless -o is given. Files
+                           ending in .8o are compiled as Octo programs, which can
+                           also be run directly
+
+Options:
+  -s, --scale <N>          Window scaling factor [default: 10]
+  -c, --speed <SPEED>      CPU speed: instructions per frame (`15`, `15ipf`),
+                           frequency (`700hz`) or `cosmac` timings [default: 10ipf]
+  -q, --quirks <PROFILE>   Interpreter behaviors: vip, chip48, schip or modern
+                           [default: modern]
+  -k, --keys <KEYS>        16 letters or digits for keypad keys 0 to F, by their
+                           position on a QWERTY keyboard whatever the actual layout
+                           [default: x123qweasdzc4rfv, the COSMAC VIP layout]
+      --keymap <FILE>      Keymap file with `KEY = CODE...` lines and per-ROM
+                           sections, applied over --keys
+                           [default: ~/.config/crab8/keymap.conf if present]
+  -p, --palette <PALETTE>  Palette name (classic, green, amber, octo, lcd, hotdog,
+                           gray, cga0, cga1 or one of --palettes), or pixel on
+                           and off colors as RRGGBB, optionally followed by
+                           XO-CHIP second plane and overlap colors
+                           [default: classic]
+      --palettes <FILE>    Palette file with `NAME = COLORS` lines and an optional
+                           `palette = NAME` default
+                           [default: ~/.config/crab8/palettes.conf if present]
+  -e, --effects <EFFECTS>  CRT effects: `crt`, `none` or amounts from 0 to 1 as
+                           `scanlines=0.3,phosphor=0.6,bloom=0.2,curvature=0.1`,
+                           toggled with F2 [default: none]
+      --blend <MODE>       Hide sprite flicker by combining the last two frames:
+                           `or` shows pixels lit on either, `blend` shows pixels
+                           lit on one of them at half intensity [default: off]
+      --headless           Run without window and print the final machine state
+      --tui                Run in the terminal, two pixels per character, quitting
+                           with Escape
+  -f, --frames <N>         Frames to run in headless mode [default: 600]
+      --wav <FILE>         Record sound into a WAV file
+      --screenshot <FILE>  Save the last headless frame as a PPM image
+      --saves <PATH>       Base path of quick save files, slot N being `<PATH>.sN`
+                           [default: ROM path]
+  -b, --break <BP>         Pause in the debugger when a breakpoint is hit, can be
+                           repeated. BP is a PC address (`2A4`), a memory watchpoint
+                           (`mem:300`), `i` to watch I, or a register condition
+                           (`v3==10`, with == != < <= > >=)
+      --pause              Start paused in the debugger
+  -d, --debug              Log every executed instruction
+  -h, --help               Print this help
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run { rom: PathBuf, options: Box<Options> },
+    Disassemble { rom: PathBuf },
+    Assemble { source: PathBuf, output: PathBuf },
+    Help,
+}
+
+// Parse command line arguments, program name excluded
+pub fn parse<I, S>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut args = args.into_iter().map(Into::into).peekable();
+    if args.next_if(|arg| arg == "disasm").is_some() {
+        return match (args.next(), args.next()) {
+            (Some(flag), _) if flag == "-h" || flag == "--help" => Ok(Command::Help),
+            (Some(rom), None) => Ok(Command::Disassemble { rom: rom.into() }),
+            (None, _) => Err("missing ROM path".to_string()),
+            (_, Some(arg)) => Err(format!("unexpected argument '{}'", arg)),
+        };
+    }
+    if args.next_if(|arg| arg == "asm").is_some() {
+        let mut source = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-o" | "--output" => {
+                    let path = args.next().ok_or("missing value for -o")?;
+                    output = Some(PathBuf::from(path));
+                }
+                _ if source.is_none() => source = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+        let source: PathBuf = source.ok_or("missing source path")?;
+        let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+        if output == source {
+            return Err(format!(
+                "output would overwrite '{}', use -o to write elsewhere",
+                source.display()
+            ));
+        }
+        return Ok(Command::Assemble { source, output });
+    }
+    let mut options = Options::default();
+    let mut rom = None;
+
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-s" | "--scale" => {
+                let scale = value(&flag)?;
+                options.scale = scale
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(|| format!("invalid scale '{}'", scale))?;
+            }
+            "-c" | "--speed" => options.speed = value(&flag)?.parse()?,
+            "-q" | "--quirks" => options.quirks = value(&flag)?.parse()?,
+            "-k" | "--keys" => options.keymap = value(&flag)?.parse()?,
+            "--keymap" => options.keymap_file = Some(PathBuf::from(value(&flag)?)),
+            "-p" | "--palette" => options.palette = Some(value(&flag)?),
+            "--palettes" => options.palette_file = Some(PathBuf::from(value(&flag)?)),
+            "-e" | "--effects" => options.effects = value(&flag)?.parse()?,
+            "--blend" => options.blend = value(&flag)?.parse()?,
+            "--headless" => options.headless = true,
+            "--tui" => options.tui = true,
+            "-f" | "--frames" => {
+                let frames = value(&flag)?;
+                options.frames = frames
+                    .parse()
+                    .map_err(|_| format!("invalid frame count '{}'", frames))?;
+            }
+            "--wav" => options.wav = Some(PathBuf::from(value(&flag)?)),
+            "--screenshot" => options.screenshot = Some(PathBuf::from(value(&flag)?)),
+            "--saves" => options.saves = Some(PathBuf::from(value(&flag)?)),
+            "-b" | "--break" => options.breakpoints.push(value(&flag)?.parse()?),
+            "--pause" => options.pause = true,
+            "-d" | "--debug" => options.debug = true,
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option '{}'", flag))
+            }
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let rom: PathBuf = rom.ok_or("missing ROM path")?;
+    options.saves.get_or_insert_with(|| rom.clone());
+    Ok(Command::Run {
+        rom,
+        options: Box::new(options),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::Breakpoint;
+    use crate::scheduler::Speed;
+
+    #[test]
+    fn parse_run() {
+        let command = parse(["--scale", "4", "--speed=700hz", "--headless", "pong.ch8"]).unwrap();
+        let Command::Run { rom, options } = command else {
+            panic!("expected run command");
+        };
+        assert_eq!(rom, PathBuf::from("pong.ch8"));
+        assert_eq!(options.scale, 4);
+        assert_eq!(options.speed, Speed::Hz(700));
+        assert!(options.headless);
+        assert_eq!(options.saves, Some(PathBuf::from("pong.ch8")));
+    }
+
+    #[test]
+    fn parse_breakpoints() {
+        let command = parse(["-b", "2a4", "--break=v0==3", "pong.ch8"]).unwrap();
+        let Command::Run { options, .. } = command else {
+            panic!("expected run command");
+        };
+        assert_eq!(options.breakpoints.len(), 2);
+        assert_eq!(options.breakpoints[0], Breakpoint::Pc(0x2a4));
+        assert_eq!(
+            parse(["-b", "mem:", "a"]),
+            Err("invalid breakpoint 'mem:'".into())
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(["--scale"]), Err("missing value for --scale".into()));
+        assert_eq!(
+            parse(["--scale", "0", "a"]),
+            Err("invalid scale '0'".into())
+        );
+        assert_eq!(
+            parse(["--turbo", "a"]),
+            Err("unknown option '--turbo'".into())
+        );
+        assert_eq!(parse(["a", "b"]), Err("unexpected argument 'b'".into()));
+        assert_eq!(
+            parse(["-k", "1234", "a"]),
+            Err("expected 16 keys, got '1234'".into())
+        );
+        assert_eq!(parse(Vec::<String>::new()), Err("missing ROM path".into()));
+        assert_eq!(parse(["-h"]), Ok(Command::Help));
+        assert_eq!(
+            parse(["disasm", "pong.ch8"]),
+            Ok(Command::Disassemble {
+                rom: "pong.ch8".into()
+            })
+        );
+        assert_eq!(parse(["disasm"]), Err("missing ROM path".into()));
+        assert_eq!(
+            parse(["asm", "test.asm"]),
+            Ok(Command::Assemble {
+                source: "test.asm".into(),
+                output: "test.ch8".into()
+            })
+        );
+        assert_eq!(parse(["asm", "-o"]), Err("missing value for -o".into()));
+        assert_eq!(
+            parse(["asm", "prog.ch8"]),
+            Err("output would overwrite 'prog.ch8', use -o to write elsewhere".into())
+        );
+    }
+}
@@ -0,0 +1,559 @@
+use std::{fmt, str::FromStr};
+
+use crate::chip8::{Chip8, Instruction, Target};
+use crate::error::Chip8Error;
+use crate::headless::MachineState;
+
+pub const HELP: &str = "\
+Commands:
+  c, continue       Resume execution
+  s, step           Execute one instruction
+  n, next           Execute one instruction, stepping over subroutine calls
+  f, finish         Run until the current subroutine returns
+  b, break <BP>     Add a breakpoint: ADDR, mem:ADDR, i or VX<op>N
+  d, delete <N>     Remove breakpoint number N
+  l, list           List breakpoints
+  r, regs           Print the machine state
+  x <ADDR> [LEN]    Dump LEN bytes of memory from ADDR [default: 16]
+  q, quit           Stop the program
+  h, help           Print this help
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    // Longest operators first so that `<=` isn't read as `<`
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn matches(self, left: u8, right: u8) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|(_, comparison)| *comparison == self)
+            .map_or("", |(symbol, _)| symbol)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Breakpoint {
+    // Stop before executing the instruction at this address
+    Pc(usize),
+    // Stop after the byte at this address is changed
+    Memory(usize),
+    // Stop after I is changed
+    I,
+    // Stop when the comparison of VX with the value becomes true
+    Register {
+        register: usize,
+        comparison: Comparison,
+        value: u8,
+    },
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(address) => write!(f, "{:03X}", address),
+            Breakpoint::Memory(address) => write!(f, "mem:{:03X}", address),
+            Breakpoint::I => write!(f, "i"),
+            Breakpoint::Register {
+                register,
+                comparison,
+                value,
+            } => write!(f, "V{:X}{}{}", register, comparison.symbol(), value),
+        }
+    }
+}
+
+// Hexadecimal address, with or without `0x`
+fn parse_address(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    usize::from_str_radix(hex, 16).ok()
+}
+
+// Decimal value, or hexadecimal with `0x`
+fn parse_value(s: &str) -> Option<u8> {
+    let s = s.trim();
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Parse `ADDR`, `mem:ADDR`, `i` or `VX<op>N` with op one of == != < <= > >=
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid breakpoint '{}'", s);
+        let lower = s.trim().to_ascii_lowercase();
+
+        if lower == "i" {
+            return Ok(Breakpoint::I);
+        }
+        if let Some(address) = lower.strip_prefix("mem:") {
+            return parse_address(address)
+                .map(Breakpoint::Memory)
+                .ok_or_else(error);
+        }
+        if let Some(condition) = lower.strip_prefix('v') {
+            if let Some((symbol, comparison)) = Comparison::OPERATORS
+                .iter()
+                .find(|(symbol, _)| condition.contains(symbol))
+            {
+                let (register, value) = condition.split_once(symbol).ok_or_else(error)?;
+                let register = usize::from_str_radix(register.trim(), 16)
+                    .ok()
+                    .filter(|&r| r < 16)
+                    .ok_or_else(error)?;
+                let value = parse_value(value).ok_or_else(error)?;
+                return Ok(Breakpoint::Register {
+                    register,
+                    comparison: *comparison,
+                    value,
+                });
+            }
+        }
+        parse_address(&lower).map(Breakpoint::Pc).ok_or_else(error)
+    }
+}
+
+// Why the debugger paused the machine
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Paused,
+    Stepped,
+    Returned,
+    Breakpoint(Breakpoint),
+    Memory { address: usize, old: u8, new: u8 },
+    I { old: u16, new: u16 },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Paused => write!(f, "paused"),
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Returned => write!(f, "returned"),
+            Stop::Breakpoint(breakpoint) => write!(f, "breakpoint {}", breakpoint),
+            Stop::Memory { address, old, new } => {
+                write!(
+                    f,
+                    "memory {:03X} changed {:02X} -> {:02X}",
+                    address, old, new
+                )
+            }
+            Stop::I { old, new } => write!(f, "I changed {:03X} -> {:03X}", old, new),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Run,
+    Pause,
+    Step,
+    // Run until PC reaches `pc` with the stack back to `depth`
+    StepOver { pc: usize, depth: usize },
+    // Run until the stack gets shallower than `depth`
+    RunToReturn { depth: usize },
+}
+
+// Outcome of a console command
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Print(String),
+    Resume,
+    Quit,
+}
+
+// Controls execution of a Chip8 through `step`, in place of `Chip8::step`
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    // Let the instruction under a PC breakpoint run when resuming from it
+    resumed: bool,
+    stop: Option<Stop>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl Debugger {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        Self {
+            breakpoints,
+            mode: Mode::Run,
+            resumed: false,
+            stop: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Pause
+    }
+
+    // Reason of the last pause, reported once
+    pub fn take_stop(&mut self) -> Option<Stop> {
+        self.stop.take()
+    }
+
+    pub fn pause(&mut self) {
+        self.halt(Stop::Paused);
+    }
+
+    pub fn resume(&mut self) {
+        self.continue_with(Mode::Run);
+    }
+
+    pub fn single_step(&mut self) {
+        self.continue_with(Mode::Step);
+    }
+
+    // Step, running a whole subroutine when the next instruction is a call
+    pub fn step_over(&mut self, chip: &Chip8) {
+        match chip.peek() {
+            Ok(Instruction(2, ..)) => self.continue_with(Mode::StepOver {
+                pc: chip.pc() + 2,
+                depth: chip.stack().len(),
+            }),
+            _ => self.single_step(),
+        }
+    }
+
+    pub fn run_to_return(&mut self, chip: &Chip8) {
+        self.continue_with(Mode::RunToReturn {
+            depth: chip.stack().len(),
+        });
+    }
+
+    fn continue_with(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resumed = true;
+        self.stop = None;
+    }
+
+    fn halt(&mut self, stop: Stop) {
+        self.mode = Mode::Pause;
+        self.stop = Some(stop);
+    }
+
+    // Execute the next instruction unless paused or stopped by a breakpoint,
+    // returning None when nothing was executed
+    pub fn step(&mut self, chip: &mut Chip8) -> Result<Option<Target>, Chip8Error> {
+        if self.is_paused() {
+            return Ok(None);
+        }
+        let resumed = std::mem::take(&mut self.resumed);
+        if !resumed && self.breakpoints.contains(&Breakpoint::Pc(chip.pc())) {
+            self.halt(Stop::Breakpoint(Breakpoint::Pc(chip.pc())));
+            return Ok(None);
+        }
+
+        let before: Vec<_> = self
+            .breakpoints
+            .iter()
+            .map(|breakpoint| watched(breakpoint, chip))
+            .collect();
+        let target = chip.step()?;
+
+        // Watchpoints and conditions take precedence over the stepping mode
+        for (breakpoint, before) in self.breakpoints.iter().zip(before) {
+            let after = watched(breakpoint, chip);
+            let stop = match *breakpoint {
+                Breakpoint::Memory(address) if before != after => Some(Stop::Memory {
+                    address,
+                    old: before as u8,
+                    new: after as u8,
+                }),
+                Breakpoint::I if before != after => Some(Stop::I {
+                    old: before as u16,
+                    new: after as u16,
+                }),
+                Breakpoint::Register { .. } if before == 0 && after == 1 => {
+                    Some(Stop::Breakpoint(*breakpoint))
+                }
+                _ => None,
+            };
+            if let Some(stop) = stop {
+                self.halt(stop);
+                return Ok(Some(target));
+            }
+        }
+
+        match self.mode {
+            Mode::Step => self.halt(Stop::Stepped),
+            Mode::StepOver { pc, depth } if chip.pc() == pc && chip.stack().len() == depth => {
+                self.halt(Stop::Stepped)
+            }
+            Mode::RunToReturn { depth } if chip.stack().len() < depth => self.halt(Stop::Returned),
+            _ => (),
+        }
+        Ok(Some(target))
+    }
+
+    // Run a console command, as typed in the headless debugger
+    pub fn command(&mut self, line: &str, chip: &Chip8) -> Result<Reply, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+
+        match (command, argument) {
+            ("c" | "continue", None) => self.resume(),
+            ("s" | "step", None) => self.single_step(),
+            ("n" | "next", None) => self.step_over(chip),
+            ("f" | "finish", None) => self.run_to_return(chip),
+            ("b" | "break", Some(breakpoint)) => {
+                self.add_breakpoint(breakpoint.parse()?);
+                return Ok(Reply::Print(format!(
+                    "breakpoint {}: {}",
+                    self.breakpoints.len() - 1,
+                    breakpoint
+                )));
+            }
+            ("d" | "delete", Some(index)) => {
+                let breakpoint = index
+                    .parse()
+                    .ok()
+                    .and_then(|index| self.remove_breakpoint(index))
+                    .ok_or_else(|| format!("no breakpoint '{}'", index))?;
+                return Ok(Reply::Print(format!("deleted {}", breakpoint)));
+            }
+            ("l" | "list", None) => {
+                let lines: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .enumerate()
+                    .map(|(i, breakpoint)| format!("{}: {}\n", i, breakpoint))
+                    .collect();
+                return Ok(Reply::Print(lines.concat()));
+            }
+            ("r" | "regs", None) => {
+                return Ok(Reply::Print(MachineState::capture(chip, 0).to_string()))
+            }
+            ("x", Some(address)) => {
+                let address = parse_address(address)
+                    .ok_or_else(|| format!("invalid address '{}'", address))?;
+                let len = match words.next() {
+                    Some(len) => len
+                        .parse()
+                        .map_err(|_| format!("invalid length '{}'", len))?,
+                    None => 16,
+                };
+                let memory = chip.memory();
+                let end = address.saturating_add(len).min(memory.len());
+                let lines: Vec<String> = memory[address.min(end)..end]
+                    .chunks(16)
+                    .enumerate()
+                    .map(|(i, bytes)| {
+                        let bytes: Vec<String> =
+                            bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                        format!("{:03X}: {}\n", address + i * 16, bytes.join(" "))
+                    })
+                    .collect();
+                return Ok(Reply::Print(lines.concat()));
+            }
+            ("q" | "quit", None) => return Ok(Reply::Quit),
+            ("h" | "help", None) => return Ok(Reply::Print(HELP.to_string())),
+            _ => return Err(format!("unknown command '{}', try help", line.trim())),
+        }
+        Ok(Reply::Resume)
+    }
+}
+
+// Value observed by a watchpoint, or whether a condition holds
+fn watched(breakpoint: &Breakpoint, chip: &Chip8) -> u32 {
+    match *breakpoint {
+        Breakpoint::Pc(_) => 0,
+        Breakpoint::Memory(address) => chip.memory().get(address).copied().unwrap_or(0) as u32,
+        Breakpoint::I => chip.i_register() as u32,
+        Breakpoint::Register {
+            register,
+            comparison,
+            value,
+        } => comparison.matches(chip.v_registers()[register], value) as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run at most `steps` instructions, returning the stop reason if any
+    fn run(debugger: &mut Debugger, chip: &mut Chip8, steps: usize) -> Option<Stop> {
+        for _ in 0..steps {
+            if debugger.step(chip).unwrap().is_none() || debugger.is_paused() {
+                break;
+            }
+        }
+        debugger.take_stop()
+    }
+
+    fn load(rom: &[u8]) -> Chip8 {
+        let mut chip = Chip8::default();
+        chip.load(rom).unwrap();
+        chip
+    }
+
+    #[test]
+    fn parse_breakpoints() {
+        assert_eq!("2a4".parse(), Ok(Breakpoint::Pc(0x2a4)));
+        assert_eq!("0x300".parse(), Ok(Breakpoint::Pc(0x300)));
+        assert_eq!("mem:F00".parse(), Ok(Breakpoint::Memory(0xf00)));
+        assert_eq!("I".parse(), Ok(Breakpoint::I));
+        assert_eq!(
+            "vA>=0x10".parse(),
+            Ok(Breakpoint::Register {
+                register: 0xa,
+                comparison: Comparison::GreaterOrEqual,
+                value: 0x10
+            })
+        );
+        assert_eq!("V3<=2".parse::<Breakpoint>().unwrap().to_string(), "V3<=2");
+        assert!("v3=2".parse::<Breakpoint>().is_err());
+        assert!("vg==2".parse::<Breakpoint>().is_err());
+        assert!("mem:".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn pc_breakpoint_and_resume() {
+        // V0 := 1; V0 := 2; V0 := 3
+        let mut chip = load(&[0x60, 0x01, 0x60, 0x02, 0x60, 0x03]);
+        let mut debugger = Debugger::new(vec![Breakpoint::Pc(0x202)]);
+        assert_eq!(
+            run(&mut debugger, &mut chip, 10),
+            Some(Stop::Breakpoint(Breakpoint::Pc(0x202)))
+        );
+        assert_eq!(chip.v_registers()[0], 1);
+        assert_eq!(debugger.step(&mut chip), Ok(None));
+        debugger.single_step();
+        assert_eq!(run(&mut debugger, &mut chip, 10), Some(Stop::Stepped));
+        assert_eq!(chip.pc(), 0x204);
+        debugger.resume();
+        assert_eq!(run(&mut debugger, &mut chip, 1), None);
+        assert_eq!(chip.v_registers()[0], 3);
+    }
+
+    #[test]
+    fn step_over_and_return() {
+        // call 206; V1 := 1; (206) V0 := 5; V0 := 6; return
+        let rom = [
+            0x22, 0x06, 0x61, 0x01, 0x00, 0x00, 0x60, 0x05, 0x60, 0x06, 0x00, 0xee,
+        ];
+        let mut chip = load(&rom);
+        let mut debugger = Debugger::default();
+        debugger.step_over(&chip);
+        assert_eq!(run(&mut debugger, &mut chip, 10), Some(Stop::Stepped));
+        assert_eq!((chip.pc(), chip.v_registers()[0]), (0x202, 6));
+
+        let mut chip = load(&rom);
+        debugger.single_step();
+        run(&mut debugger, &mut chip, 10);
+        assert_eq!(chip.pc(), 0x206);
+        debugger.run_to_return(&chip);
+        assert_eq!(run(&mut debugger, &mut chip, 10), Some(Stop::Returned));
+        assert_eq!(chip.pc(), 0x202);
+    }
+
+    #[test]
+    fn watchpoints_and_conditions() {
+        // I := 300; V0 := 7; save V0; V0 += 1 forever
+        let rom = [0xa3, 0x00, 0x60, 0x07, 0xf0, 0x55, 0x70, 0x01, 0x12, 0x06];
+        let mut chip = load(&rom);
+        let mut debugger = Debugger::new(vec![
+            Breakpoint::I,
+            Breakpoint::Memory(0x300),
+            "v0==10".parse().unwrap(),
+        ]);
+        assert_eq!(
+            run(&mut debugger, &mut chip, 10),
+            Some(Stop::I { old: 0, new: 0x300 })
+        );
+        debugger.remove_breakpoint(0);
+        debugger.resume();
+        assert_eq!(
+            run(&mut debugger, &mut chip, 10),
+            Some(Stop::Memory {
+                address: 0x300,
+                old: 0,
+                new: 7
+            })
+        );
+        debugger.resume();
+        assert_eq!(
+            run(&mut debugger, &mut chip, 100),
+            Some(Stop::Breakpoint(debugger.breakpoints()[1]))
+        );
+        assert_eq!(chip.v_registers()[0], 10);
+    }
+
+    #[test]
+    fn console_commands() {
+        let chip = load(&[0x60, 0x01]);
+        let mut debugger = Debugger::default();
+        debugger.pause();
+        assert_eq!(
+            debugger.command("b 2a0", &chip),
+            Ok(Reply::Print("breakpoint 0: 2a0".into()))
+        );
+        assert_eq!(
+            debugger.command("l", &chip),
+            Ok(Reply::Print("0: 2A0\n".into()))
+        );
+        assert_eq!(
+            debugger.command("x 200 2", &chip),
+            Ok(Reply::Print("200: 60 01\n".into()))
+        );
+        assert!(debugger
+            .command("x 200 18446744073709551615", &chip)
+            .is_ok());
+        assert!(debugger.command("d 1", &chip).is_err());
+        assert!(debugger.command("jump", &chip).is_err());
+        assert_eq!(debugger.command("q", &chip), Ok(Reply::Quit));
+        assert!(debugger.is_paused());
+        assert_eq!(debugger.command("c", &chip), Ok(Reply::Resume));
+        assert!(!debugger.is_paused());
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt,
+};
+
+use crate::chip8::{Instruction, LOAD_START};
+
+// Data bytes listed on each `db` line
+const DATA_PER_LINE: usize = 8;
+
+// One line of the listing
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Code {
+        address: usize,
+        bytes: Vec<u8>,
+        text: String,
+    },
+    Data {
+        address: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Item {
+    pub fn address(&self) -> usize {
+        match self {
+            Item::Code { address, .. } | Item::Data { address, .. } => *address,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disassembly {
+    items: Vec<Item>,
+    // Jump, call and I targets starting an item of the ROM
+    labels: BTreeSet<usize>,
+}
+
+impl Disassembly {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn labels(&self) -> &BTreeSet<usize> {
+        &self.labels
+    }
+}
+
+pub fn label(address: usize) -> String {
+    format!("L{:03X}", address)
+}
+
+// Listing that the assembler reads back, with addresses and opcodes in comments
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            if self.labels.contains(&item.address()) {
+                writeln!(f, "{}:", label(item.address()))?;
+            }
+            let (text, bytes) = match item {
+                Item::Code { text, bytes, .. } => (text.clone(), bytes),
+                Item::Data { bytes, .. } => {
+                    let values: Vec<String> =
+                        bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    (format!("db {}", values.join(", ")), bytes)
+                }
+            };
+            let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(f, "    {:<32}; {:03X}: {}", text, item.address(), hex)?;
+        }
+        Ok(())
+    }
+}
+
+// Decode the instruction at the start of `bytes`, returning its text and size.
+// `name` formats target addresses.
+fn decode(bytes: &[u8], name: &dyn Fn(usize) -> String) -> Option<(String, usize)> {
+    let opcode = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]);
+    let instruction = Instruction::decode(opcode);
+    let nnn = usize::from(&instruction);
+    let nn = u8::from(&instruction);
+    let Instruction(_, x, y, n) = instruction;
+
+    let text = match instruction {
+        Instruction(0, 0, 0xe, 0) => "CLS".to_string(),
+        Instruction(0, 0, 0xe, 0xe) => "RET".to_string(),
+        Instruction(0, 0, 0xc, n) => format!("SCD {}", n),
+        Instruction(0, 0, 0xd, n) => format!("SCU {}", n),
+        Instruction(0, 0, 0xf, 0xb) => "SCR".to_string(),
+        Instruction(0, 0, 0xf, 0xc) => "SCL".to_string(),
+        Instruction(0, 0, 0xf, 0xd) => "EXIT".to_string(),
+        Instruction(0, 0, 0xf, 0xe) => "LOW".to_string(),
+        Instruction(0, 0, 0xf, 0xf) => "HIGH".to_string(),
+        Instruction(1, ..) => format!("JP {}", name(nnn)),
+        Instruction(2, ..) => format!("CALL {}", name(nnn)),
+        Instruction(3, ..) => format!("SE V{:X}, 0x{:02X}", x, nn),
+        Instruction(4, ..) => format!("SNE V{:X}, 0x{:02X}", x, nn),
+        Instruction(5, .., 0) => format!("SE V{:X}, V{:X}", x, y),
+        Instruction(5, .., 2) => format!("SAVE V{:X}, V{:X}", x, y),
+        Instruction(5, .., 3) => format!("LOAD V{:X}, V{:X}", x, y),
+        Instruction(6, ..) => format!("LD V{:X}, 0x{:02X}", x, nn),
+        Instruction(7, ..) => format!("ADD V{:X}, 0x{:02X}", x, nn),
+        Instruction(8, .., n @ (0..=7 | 0xe)) => {
+            let operation = ["LD", "OR", "AND", "XOR", "ADD", "SUB", "SHR", "SUBN"]
+                .get(n as usize)
+                .unwrap_or(&"SHL");
+            format!("{} V{:X}, V{:X}", operation, x, y)
+        }
+        Instruction(9, .., 0) => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction(0xa, ..) => format!("LD I, {}", name(nnn)),
+        Instruction(0xb, ..) => format!("JP V0, {}", name(nnn)),
+        Instruction(0xc, ..) => format!("RND V{:X}, 0x{:02X}", x, nn),
+        Instruction(0xd, ..) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction(0xe, _, 9, 0xe) => format!("SKP V{:X}", x),
+        Instruction(0xe, _, 0xa, 1) => format!("SKNP V{:X}", x),
+        Instruction(0xf, 0, 0, 0) => {
+            let address = u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?]);
+            return Some((format!("LD I, LONG {}", name(address as usize)), 4));
+        }
+        Instruction(0xf, n, 0, 1) => format!("PLANE {}", n),
+        Instruction(0xf, 0, 0, 2) => "AUDIO".to_string(),
+        Instruction(0xf, _, 0, 7) => format!("LD V{:X}, DT", x),
+        Instruction(0xf, _, 0, 0xa) => format!("LD V{:X}, K", x),
+        Instruction(0xf, _, 1, 5) => format!("LD DT, V{:X}", x),
+        Instruction(0xf, _, 1, 8) => format!("LD ST, V{:X}", x),
+        Instruction(0xf, _, 1, 0xe) => format!("ADD I, V{:X}", x),
+        Instruction(0xf, _, 2, 9) => format!("LD F, V{:X}", x),
+        Instruction(0xf, _, 3, 0) => format!("LD HF, V{:X}", x),
+        Instruction(0xf, _, 3, 3) => format!("LD B, V{:X}", x),
+        Instruction(0xf, _, 3, 0xa) => format!("PITCH V{:X}", x),
+        Instruction(0xf, _, 5, 5) => format!("LD [I], V{:X}", x),
+        Instruction(0xf, _, 6, 5) => format!("LD V{:X}, [I]", x),
+        Instruction(0xf, _, 7, 5) => format!("LD R, V{:X}", x),
+        Instruction(0xf, _, 8, 5) => format!("LD V{:X}, R", x),
+        _ => return None,
+    };
+    Some((text, 2))
+}
+
+// Mnemonic of the instruction at the start of `bytes`, with its size in bytes
+pub fn mnemonic(bytes: &[u8]) -> Option<(String, usize)> {
+    decode(bytes, &|address| format!("0x{:03X}", address))
+}
+
+// Addresses execution may continue to after the instruction at `address`,
+// and addresses it refers to
+struct Flow {
+    next: Vec<usize>,
+    targets: Vec<usize>,
+}
+
+fn flow(rom: &[u8], offset: usize, size: usize) -> Flow {
+    let address = LOAD_START + offset;
+    let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+    let instruction = Instruction::decode(opcode);
+    let nnn = usize::from(&instruction);
+    // Skips jump over both halves of a long I load
+    let skipped = match rom.get(offset + size..offset + size + 2) {
+        Some([0xf0, 0x00]) => 4,
+        _ => 2,
+    };
+
+    let (next, targets) = match instruction {
+        Instruction(0, 0, 0xe, 0xe) | Instruction(0, 0, 0xf, 0xd) => (vec![], vec![]),
+        Instruction(1, ..) => (vec![nnn], vec![nnn]),
+        Instruction(2, ..) => (vec![nnn, address + size], vec![nnn]),
+        // Jump tables: follow the first entry
+        Instruction(0xb, ..) => (vec![nnn], vec![nnn]),
+        Instruction(3 | 4, ..)
+        | Instruction(5 | 9, .., 0)
+        | Instruction(0xe, _, 9, 0xe)
+        | Instruction(0xe, _, 0xa, 1) => (vec![address + size, address + size + skipped], vec![]),
+        Instruction(0xa, ..) => (vec![address + size], vec![nnn]),
+        Instruction(0xf, 0, 0, 0) => {
+            let target = u16::from_be_bytes([rom[offset + 2], rom[offset + 3]]) as usize;
+            (vec![address + size], vec![target])
+        }
+        _ => (vec![address + size], vec![]),
+    };
+    Flow { next, targets }
+}
+
+// Split `rom` (loaded at 0x200) into code and data by following every path
+// reachable from the entry point
+pub fn disassemble(rom: &[u8]) -> Disassembly {
+    let end = LOAD_START + rom.len();
+    // Size of the instruction starting at each offset, if reached as code
+    let mut code: Vec<Option<usize>> = vec![None; rom.len()];
+    let mut labels = BTreeSet::new();
+    let mut queue = VecDeque::from([LOAD_START]);
+
+    while let Some(address) = queue.pop_front() {
+        if !(LOAD_START..end).contains(&address) {
+            continue;
+        }
+        let offset = address - LOAD_START;
+        if code[offset].is_some() {
+            continue;
+        }
+        let Some((_, size)) = mnemonic(&rom[offset..]) else {
+            continue;
+        };
+        code[offset] = Some(size);
+        let flow = flow(rom, offset, size);
+        labels.extend(
+            flow.targets
+                .into_iter()
+                .filter(|target| (LOAD_START..end).contains(target)),
+        );
+        queue.extend(flow.next);
+    }
+
+    // Items as offset, size and whether they are code
+    let mut layout = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        if let Some(size) = code[offset] {
+            layout.push((offset, size, true));
+            offset += size;
+            continue;
+        }
+        // Data runs until the next code, label or full line
+        let mut len = 1;
+        while offset + len < rom.len()
+            && len < DATA_PER_LINE
+            && code[offset + len].is_none()
+            && !labels.contains(&(LOAD_START + offset + len))
+        {
+            len += 1;
+        }
+        layout.push((offset, len, false));
+        offset += len;
+    }
+    // Targets inside another instruction cannot be labeled, they are kept as
+    // raw addresses
+    let starts: BTreeSet<usize> = layout
+        .iter()
+        .map(|&(offset, _, _)| LOAD_START + offset)
+        .collect();
+    labels.retain(|address| starts.contains(address));
+
+    let name = |address: usize| {
+        if labels.contains(&address) {
+            label(address)
+        } else {
+            format!("0x{:03X}", address)
+        }
+    };
+    let items = layout
+        .into_iter()
+        .map(|(offset, size, is_code)| {
+            let address = LOAD_START + offset;
+            let bytes = rom[offset..offset + size].to_vec();
+            if is_code {
+                let (text, _) = decode(&rom[offset..], &name).unwrap();
+                Item::Code {
+                    address,
+                    bytes,
+                    text,
+                }
+            } else {
+                Item::Data { address, bytes }
+            }
+        })
+        .collect();
+
+    Disassembly { items, labels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonics() {
+        let text = |bytes: &[u8]| mnemonic(bytes).map(|(text, _)| text);
+        assert_eq!(text(&[0x00, 0xe0]).as_deref(), Some("CLS"));
+        assert_eq!(text(&[0x12, 0x4e]).as_deref(), Some("JP 0x24E"));
+        assert_eq!(text(&[0x8a, 0xb6]).as_deref(), Some("SHR VA, VB"));
+        assert_eq!(text(&[0x8a, 0xbe]).as_deref(), Some("SHL VA, VB"));
+        assert_eq!(text(&[0xd1, 0x25]).as_deref(), Some("DRW V1, V2, 5"));
+        assert_eq!(text(&[0xf3, 0x65]).as_deref(), Some("LD V3, [I]"));
+        assert_eq!(text(&[0x8a, 0xb8]), None);
+        assert_eq!(text(&[0xf0]), None);
+        assert_eq!(
+            mnemonic(&[0xf0, 0x00, 0x12, 0x34]),
+            Some(("LD I, LONG 0x1234".to_string(), 4))
+        );
+    }
+
+    #[test]
+    fn flow_analysis() {
+        // 200: call 208; jump 206; (204) data; (206) loop; (208) I := 20C; return; (20C) sprite
+        let rom = [
+            0x22, 0x08, 0x12, 0x06, 0xff, 0xff, 0x12, 0x06, 0xa2, 0x0c, 0x00, 0xee, 0x80, 0xc0,
+        ];
+        let disassembly = disassemble(&rom);
+        assert_eq!(
+            disassembly.labels().iter().copied().collect::<Vec<_>>(),
+            [0x206, 0x208, 0x20c]
+        );
+        let kinds: Vec<(usize, bool)> = disassembly
+            .items()
+            .iter()
+            .map(|item| (item.address(), matches!(item, Item::Code { .. })))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (0x200, true),
+                (0x202, true),
+                (0x204, false),
+                (0x206, true),
+                (0x208, true),
+                (0x20a, true),
+                (0x20c, false)
+            ]
+        );
+        let listing = disassembly.to_string();
+        assert!(listing.contains("    CALL L208"));
+        assert!(listing.contains("L20C:\n    db 0x80, 0xC0"));
+    }
+
+    #[test]
+    fn skips_over_long_load() {
+        // if V0 == 0 then I := long 300; return
+        let rom = [0x30, 0x00, 0xf0, 0x00, 0x03, 0x00, 0x00, 0xee];
+        let items = disassemble(&rom).items().to_vec();
+        assert_eq!(items.len(), 3);
+        assert!(items.iter().all(|item| matches!(item, Item::Code { .. })));
+    }
+}
@@ -0,0 +1,47 @@
+use std::{error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chip8Error {
+    // Opcode not part of the instruction set, `address` is where it was fetched
+    UnknownOpcode { opcode: u16, address: usize },
+    // `00EE` with an empty stack
+    StackUnderflow { address: usize },
+    // `2NNN` with a full stack
+    StackOverflow { address: usize },
+    // Read or write at `target`, past the end of memory
+    OutOfBounds { target: usize, address: usize },
+    RomTooLarge { size: usize, max: usize },
+    // Save state that cannot be restored
+    InvalidState { reason: String },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, address } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, address)
+            }
+            Chip8Error::StackUnderflow { address } => {
+                write!(f, "return with empty stack at {:03X}", address)
+            }
+            Chip8Error::StackOverflow { address } => {
+                write!(f, "stack overflow on call at {:03X}", address)
+            }
+            Chip8Error::OutOfBounds { target, address } => write!(
+                f,
+                "memory access out of bounds ({:X}) at {:03X}",
+                target, address
+            ),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(
+                    f,
+                    "ROM is {} bytes, at most {} bytes fit in memory",
+                    size, max
+                )
+            }
+            Chip8Error::InvalidState { reason } => write!(f, "invalid save state: {}", reason),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
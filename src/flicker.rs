@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+// Palette index flag of pixels blended with the previous frame, whose index
+// is kept in bits 2 and 3
+pub const BLENDED: u8 = 0x80;
+
+// How the last two frames are combined. CHIP-8 games erase sprites before
+// drawing them again, so a sprite moving every frame is missing from half of
+// the frames; combining frames shows it on all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameBlend {
+    // Last frame only
+    #[default]
+    Off,
+    // Pixels lit on either frame
+    Or,
+    // Pixels lit on a single frame at half intensity
+    Blend,
+}
+
+impl FromStr for FrameBlend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "none" => Ok(Self::Off),
+            "or" => Ok(Self::Or),
+            "blend" => Ok(Self::Blend),
+            _ => Err(format!(
+                "invalid frame blending '{}', expected off, or or blend",
+                s
+            )),
+        }
+    }
+}
+
+// Screen to present, made of the palette indices of the last two frames
+#[derive(Clone, Debug, Default)]
+pub struct AntiFlicker {
+    mode: FrameBlend,
+    previous: Vec<Vec<u8>>,
+    output: Vec<Vec<u8>>,
+}
+
+impl AntiFlicker {
+    pub fn new(mode: FrameBlend) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn mode(&self) -> FrameBlend {
+        self.mode
+    }
+
+    // Screen at the end of an emulated frame, as returned by `Chip8::planes`.
+    // Returns whether the output changed.
+    pub fn push(&mut self, planes: Vec<Vec<u8>>) -> bool {
+        // Nothing to combine with after a resolution change
+        let previous = if self.previous.len() == planes.len() {
+            &self.previous
+        } else {
+            &planes
+        };
+        let output: Vec<Vec<u8>> = planes
+            .iter()
+            .zip(previous)
+            .map(|(row, previous_row)| {
+                row.iter()
+                    .zip(previous_row)
+                    .map(|(&current, &previous)| match self.mode {
+                        FrameBlend::Off => current,
+                        FrameBlend::Or => current | previous,
+                        FrameBlend::Blend if current == previous => current,
+                        FrameBlend::Blend => BLENDED | previous << 2 | current,
+                    })
+                    .collect()
+            })
+            .collect();
+        self.previous = planes;
+        let changed = output != self.output;
+        self.output = output;
+        changed
+    }
+
+    pub fn output(&self) -> &[Vec<u8>] {
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_blending() {
+        let mut or = AntiFlicker::new(FrameBlend::Or);
+        let mut blend = AntiFlicker::new(FrameBlend::Blend);
+        for anti in [&mut or, &mut blend] {
+            assert!(anti.push(vec![vec![0, 1, 1, 0]]));
+            // Sprite erased, then drawn one pixel further
+            anti.push(vec![vec![0, 0, 0, 0]]);
+        }
+        assert_eq!(or.output(), [[0, 1, 1, 0]]);
+        assert_eq!(blend.output(), [[0, BLENDED | 4, BLENDED | 4, 0]]);
+        assert!(or.push(vec![vec![0, 0, 1, 1]]));
+        assert_eq!(or.output(), [[0, 0, 1, 1]]);
+        assert!(!or.push(vec![vec![0, 0, 1, 1]]));
+
+        // Hires switch
+        assert!(or.push(vec![vec![2; 8]; 2]));
+        assert_eq!(or.output(), vec![vec![2; 8]; 2]);
+        assert!("xor".parse::<FrameBlend>().is_err());
+    }
+}
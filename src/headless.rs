@@ -0,0 +1,205 @@
+use std::fmt;
+
+use crate::audio::Audio;
+use crate::chip8::{Chip8, Target};
+use crate::debugger::{Debugger, Reply};
+use crate::error::Chip8Error;
+use crate::input::{self, InputDevice};
+use crate::keymap::Keymap;
+use crate::options::Options;
+use crate::scheduler::{Scheduler, DEFAULT_INSTRUCTIONS_PER_FRAME};
+
+// Instructions executed between two timer ticks when running by cycles
+pub const CYCLES_PER_FRAME: usize = DEFAULT_INSTRUCTIONS_PER_FRAME as usize;
+
+// Copy of the machine observable state at a given time
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineState {
+    pub pixels: Vec<Vec<bool>>,
+    pub pc: usize,
+    pub i_register: u16,
+    pub v_registers: [u8; 16],
+    pub memory: Vec<u8>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub cycles: usize,
+}
+
+impl MachineState {
+    pub fn capture(chip: &Chip8, cycles: usize) -> Self {
+        Self {
+            pixels: chip.pixels(),
+            pc: chip.pc(),
+            i_register: chip.i_register(),
+            v_registers: chip.v_registers(),
+            memory: chip.memory().to_vec(),
+            delay_timer: chip.delay_timer(),
+            sound_timer: chip.sound_timer(),
+            cycles,
+        }
+    }
+}
+
+// Framebuffer as text (`#` on, `.` off) followed by the registers
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in &self.pixels {
+            let line: String = row.iter().map(|&p| if p { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(
+            f,
+            "PC={:03X} I={:03X} DT={:02X} ST={:02X} cycles={}",
+            self.pc, self.i_register, self.delay_timer, self.sound_timer, self.cycles
+        )?;
+        let registers: Vec<String> = self
+            .v_registers
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X}={:02X}", i, v))
+            .collect();
+        writeln!(f, "{}", registers.join(" "))
+    }
+}
+
+// Drive a Chip8 without any window, GPU or event loop
+pub struct Headless {
+    chip: Chip8,
+    scheduler: Scheduler,
+    audio: Option<Audio>,
+    debugger: Option<Debugger>,
+    // Gamepad polled at the start of each frame
+    input: Option<(Box<dyn InputDevice>, Keymap)>,
+    cycles: usize,
+}
+
+impl Headless {
+    pub fn new(rom: &[u8]) -> Result<Self, Chip8Error> {
+        Self::with_options(rom, &Options::default())
+    }
+
+    // Use speed and quirks from `options`
+    pub fn with_options(rom: &[u8], options: &Options) -> Result<Self, Chip8Error> {
+        let mut chip = Chip8::new(options.quirks);
+        chip.load(rom)?;
+        Ok(Self {
+            chip,
+            scheduler: Scheduler::new(options.speed),
+            audio: None,
+            debugger: None,
+            input: None,
+            cycles: 0,
+        })
+    }
+
+    // Generate sound while running by frames
+    pub fn set_audio(&mut self, audio: Audio) {
+        self.audio = Some(audio);
+    }
+
+    // Run frames through the debugger
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    // Feed keypad keys from a gamepad while running by frames
+    pub fn set_input(&mut self, device: Box<dyn InputDevice>, keymap: Keymap) {
+        self.input = Some((device, keymap));
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    // Run a debugger console command, see `debugger::HELP`
+    pub fn command(&mut self, line: &str) -> Result<Reply, String> {
+        match self.debugger.as_mut() {
+            Some(debugger) => debugger.command(line, &self.chip),
+            None => Err("debugger is not enabled".to_string()),
+        }
+    }
+
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    pub fn chip_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    // Execute `cycles` instructions, or until the program exits, and return the
+    // number of frames drawn.
+    // Timers are ticked every CYCLES_PER_FRAME instructions, as if running at 60 Hz.
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<usize, Chip8Error> {
+        let mut draws = 0;
+        for _ in 0..cycles {
+            match self.chip.step()? {
+                Target::Pixels => draws += 1,
+                Target::Exit => break,
+                Target::Memory => (),
+            }
+            self.cycles += 1;
+            if self.cycles.is_multiple_of(CYCLES_PER_FRAME) {
+                self.chip.tick_timers();
+            }
+        }
+        Ok(draws)
+    }
+
+    // Execute `frames` frames at the configured speed, ticking timers once per frame,
+    // and return the number of frames run.
+    // Stop early if the program exits or the debugger pauses.
+    pub fn run_frames(&mut self, frames: usize) -> Result<usize, Chip8Error> {
+        for count in 0..frames {
+            if self.debugger.as_ref().is_some_and(Debugger::is_paused) {
+                return Ok(count);
+            }
+            if let Some((device, keymap)) = self.input.as_mut() {
+                input::update_keys(device.as_mut(), keymap, &mut self.chip);
+            }
+            let frame = self
+                .scheduler
+                .run_frame_with(&mut self.chip, self.debugger.as_mut())?;
+            self.cycles += frame.instructions;
+            // Timers are frozen while paused
+            if frame.exited || frame.paused {
+                return Ok(count + 1);
+            }
+            if let Some(audio) = self.audio.as_mut() {
+                audio.frame(&self.chip);
+            }
+            self.chip.tick_timers();
+        }
+        Ok(frames)
+    }
+
+    pub fn state(&self) -> MachineState {
+        MachineState::capture(&self.chip, self.cycles)
+    }
+}
+
+// Load `rom`, execute it for `cycles` instructions and return the final state
+pub fn run(rom: &[u8], cycles: usize) -> Result<MachineState, Chip8Error> {
+    let mut headless = Headless::new(rom)?;
+    headless.run_cycles(cycles)?;
+    Ok(headless.state())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::Breakpoint;
+
+    #[test]
+    fn breakpoint_freezes_timers() {
+        // V0 := 10; delay := V0; loop
+        let mut headless = Headless::new(&[0x60, 0x0a, 0xf0, 0x15, 0x12, 0x04]).unwrap();
+        headless.set_debugger(Debugger::new(vec![Breakpoint::Pc(0x204)]));
+        assert_eq!(headless.run_frames(5), Ok(1));
+        assert_eq!(headless.chip().delay_timer(), 10);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::chip8::Chip8;
+use crate::keymap::Keymap;
+
+// Gamepad buttons, bound in keymaps as `Gamepad` followed by the variant name
+// (`GamepadDPadUp`, `GamepadSouth`...)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    // Action buttons by position, A/Cross being South on most gamepads
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub fn code(self) -> String {
+        format!("Gamepad{:?}", self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub pressed: bool,
+}
+
+// Source of gamepad button changes, polled once per frame
+pub trait InputDevice {
+    fn poll(&mut self) -> Vec<ButtonEvent>;
+}
+
+// Press or release the keypad keys bound to the buttons changed on `device`
+pub fn update_keys(device: &mut dyn InputDevice, keymap: &Keymap, chip: &mut Chip8) {
+    for event in device.poll() {
+        if let Some(key) = keymap.key(&event.button.code()) {
+            chip.set_key(key, event.pressed);
+        }
+    }
+}
+
+// Virtual gamepad returning the presses and releases queued since the last
+// poll. Clones share the same queue, so one can be kept to script the input
+// of a device handed over to a frontend.
+#[derive(Clone, Debug, Default)]
+pub struct MockDevice {
+    events: Rc<RefCell<VecDeque<ButtonEvent>>>,
+}
+
+impl MockDevice {
+    pub fn press(&self, button: Button) {
+        self.events.borrow_mut().push_back(ButtonEvent {
+            button,
+            pressed: true,
+        });
+    }
+
+    pub fn release(&self, button: Button) {
+        self.events.borrow_mut().push_back(ButtonEvent {
+            button,
+            pressed: false,
+        });
+    }
+}
+
+impl InputDevice for MockDevice {
+    fn poll(&mut self) -> Vec<ButtonEvent> {
+        self.events.borrow_mut().drain(..).collect()
+    }
+}
+
+// Every gamepad connected, through gilrs
+#[cfg(feature = "gamepad")]
+pub struct Gamepads {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = gilrs::Gilrs::new().map_err(|e| e.to_string())?;
+        Ok(Self { gilrs })
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl InputDevice for Gamepads {
+    fn poll(&mut self) -> Vec<ButtonEvent> {
+        use gilrs::{Button as Pad, EventType};
+
+        let mut events = Vec::new();
+        while let Some(event) = self.gilrs.next_event() {
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                _ => continue,
+            };
+            let button = match button {
+                Pad::DPadUp => Button::DPadUp,
+                Pad::DPadDown => Button::DPadDown,
+                Pad::DPadLeft => Button::DPadLeft,
+                Pad::DPadRight => Button::DPadRight,
+                Pad::South => Button::South,
+                Pad::East => Button::East,
+                Pad::North => Button::North,
+                Pad::West => Button::West,
+                Pad::LeftTrigger | Pad::LeftTrigger2 => Button::LeftTrigger,
+                Pad::RightTrigger | Pad::RightTrigger2 => Button::RightTrigger,
+                Pad::Select => Button::Select,
+                Pad::Start => Button::Start,
+                _ => continue,
+            };
+            events.push(ButtonEvent { button, pressed });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_device_drives_keypad() {
+        let mut keymap = Keymap::default();
+        keymap.apply("1 = GamepadSouth", "").unwrap();
+        let device = MockDevice::default();
+        let mut input = device.clone();
+
+        // V0 := key
+        let mut chip = Chip8::default();
+        chip.load(&[0xf0, 0x0a]).unwrap();
+        chip.step().unwrap();
+        device.press(Button::South);
+        update_keys(&mut input, &keymap, &mut chip);
+        chip.step().unwrap();
+        device.release(Button::South);
+        device.press(Button::Select);
+        update_keys(&mut input, &keymap, &mut chip);
+        chip.step().unwrap();
+        assert_eq!((chip.pc(), chip.v_registers()[0]), (0x202, 1));
+        assert!(input.poll().is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{fs, path::Path, str::FromStr};
+
+// File read from the configuration directory when no --keymap is given
+pub const KEYMAP_FILE: &str = "keymap.conf";
+
+// COSMAC VIP keypad, row by row, on the same positions of a QWERTY keyboard
+//   1 2 3 C      1 2 3 4
+//   4 5 6 D  ->  Q W E R
+//   7 8 9 E      A S D F
+//   A 0 B F      Z X C V
+const COSMAC_LAYOUT: [(u8, &str); 16] = [
+    (0x1, "Digit1"),
+    (0x2, "Digit2"),
+    (0x3, "Digit3"),
+    (0xc, "Digit4"),
+    (0x4, "KeyQ"),
+    (0x5, "KeyW"),
+    (0x6, "KeyE"),
+    (0xd, "KeyR"),
+    (0x7, "KeyA"),
+    (0x8, "KeyS"),
+    (0x9, "KeyD"),
+    (0xe, "KeyF"),
+    (0xa, "KeyZ"),
+    (0x0, "KeyX"),
+    (0xb, "KeyC"),
+    (0xf, "KeyV"),
+];
+
+// D-pad on the 2 4 6 8 cross used by many games, see `input::Button` for names
+const GAMEPAD_LAYOUT: [(u8, &str); 9] = [
+    (0x2, "GamepadDPadUp"),
+    (0x8, "GamepadDPadDown"),
+    (0x4, "GamepadDPadLeft"),
+    (0x6, "GamepadDPadRight"),
+    (0x5, "GamepadSouth"),
+    (0x0, "GamepadEast"),
+    (0xa, "GamepadWest"),
+    (0xb, "GamepadNorth"),
+    (0xf, "GamepadStart"),
+];
+
+// Physical keys and gamepad buttons bound to each keypad key. Keys are named
+// by their position, with the W3C `code` names (`KeyQ`, `Digit1`, `ArrowUp`,
+// `Numpad0`...), so the layout doesn't depend on the keyboard language or
+// modifiers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    // Indexed by keypad key
+    bindings: Vec<Vec<String>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::cosmac()
+    }
+}
+
+// Letters and digits stand for their QWERTY position, anything else must be
+// a code name
+pub(crate) fn parse_code(s: &str) -> Result<String, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_digit() => Ok(format!("Digit{}", c)),
+        (Some(c), None) if c.is_ascii_alphabetic() => Ok(format!("Key{}", c.to_ascii_uppercase())),
+        (Some(c), Some(_))
+            if c.is_ascii_uppercase() && s.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Ok(s.to_string())
+        }
+        _ => Err(format!("invalid key '{}'", s)),
+    }
+}
+
+impl Keymap {
+    pub fn cosmac() -> Self {
+        let mut keymap = Self {
+            bindings: vec![Vec::new(); 16],
+        };
+        for (key, code) in COSMAC_LAYOUT.into_iter().chain(GAMEPAD_LAYOUT) {
+            keymap.bindings[key as usize].push(code.to_string());
+        }
+        keymap
+    }
+
+    pub fn bindings(&self, key: u8) -> &[String] {
+        &self.bindings[key as usize & 0xf]
+    }
+
+    // Replace the physical keys of keypad `key`, unbinding them from other keys.
+    // Keyboard keys only replace keyboard keys and gamepad buttons gamepad
+    // buttons, so remapping one keeps the other bound.
+    pub fn bind(&mut self, key: u8, mut codes: Vec<String>) {
+        for bindings in self.bindings.iter_mut() {
+            bindings.retain(|code| !codes.contains(code));
+        }
+        let is_gamepad = |code: &String| code.starts_with("Gamepad");
+        let replaced = (
+            codes.iter().any(|code| !is_gamepad(code)),
+            codes.iter().any(is_gamepad),
+        );
+        let bindings = &mut self.bindings[key as usize & 0xf];
+        codes.extend(bindings.drain(..).filter(|code| {
+            if is_gamepad(code) {
+                !replaced.1
+            } else {
+                !replaced.0
+            }
+        }));
+        *bindings = codes;
+    }
+
+    // Keypad key bound to a physical key
+    pub fn key(&self, code: &str) -> Option<u8> {
+        self.bindings
+            .iter()
+            .position(|codes| codes.iter().any(|c| c == code))
+            .map(|key| key as u8)
+    }
+
+    // Apply a keymap file, made of `KEY = CODE...` lines binding keypad key 0
+    // to F. Lines before any `[section]` and in `[default]` apply to every ROM,
+    // those of a section named after the ROM file (`[pong.ch8]`) to that ROM
+    // only, overriding the defaults. Comments start with `#`.
+    pub fn apply(&mut self, config: &str, rom: &str) -> Result<(), String> {
+        let mut defaults = Vec::new();
+        let mut overrides = Vec::new();
+        let mut section = "default";
+        for (index, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .map(str::trim)
+                    .ok_or_else(|| error(format!("invalid section '{}'", line)))?;
+                continue;
+            }
+            let (key, codes) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected 'KEY = CODE...', got '{}'", line)))?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| error(format!("invalid keypad key '{}'", key.trim())))?;
+            let codes = codes
+                .split_whitespace()
+                .map(parse_code)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            if section == "default" {
+                defaults.push((key, codes));
+            } else if section == rom {
+                overrides.push((key, codes));
+            }
+        }
+        for (key, codes) in defaults.into_iter().chain(overrides) {
+            self.bind(key, codes);
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, path: P, rom: &str) -> Result<(), String> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read keymap '{}': {}", path.display(), e))?;
+        self.apply(&config, rom)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// 16 letters or digits for keypad keys 0 to F, by their QWERTY position, with
+// the default gamepad layout
+impl FromStr for Keymap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let codes = s
+            .chars()
+            .map(|c| parse_code(&c.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if codes.len() != 16 {
+            return Err(format!("expected 16 keys, got '{}'", s));
+        }
+        let mut keymap = Self {
+            bindings: vec![Vec::new(); 16],
+        };
+        for (key, code) in codes.into_iter().enumerate() {
+            keymap.bindings[key].push(code);
+        }
+        for (key, code) in GAMEPAD_LAYOUT {
+            keymap.bindings[key as usize].push(code.to_string());
+        }
+        Ok(keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosmac_layout() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key("Digit1"), Some(1));
+        assert_eq!(keymap.key("Digit4"), Some(0xc));
+        assert_eq!(keymap.key("KeyX"), Some(0));
+        assert_eq!(keymap.key("KeyV"), Some(0xf));
+        assert_eq!(keymap.key("KeyP"), None);
+        assert_eq!(keymap.bindings(0xa), ["KeyZ", "GamepadWest"]);
+        assert_eq!(keymap.key("GamepadDPadUp"), Some(2));
+        assert_eq!("x123qweasdzc4rfv".parse::<Keymap>(), Ok(keymap));
+    }
+
+    #[test]
+    fn keymap_file() {
+        let config = "
+            # Arrows for everything
+            5 = w ArrowUp
+            [pong.ch8]
+            1 = KeyA   # takes A away from 7
+            [other.ch8]
+            2 = KeyB
+        ";
+        let mut keymap = Keymap::default();
+        keymap.apply(config, "pong.ch8").unwrap();
+        assert_eq!(keymap.bindings(5), ["KeyW", "ArrowUp", "GamepadSouth"]);
+        assert_eq!(keymap.key("ArrowUp"), Some(5));
+        assert_eq!(keymap.key("KeyA"), Some(1));
+        assert!(keymap.bindings(7).is_empty());
+        assert_eq!(keymap.key("KeyB"), None);
+    }
+
+    #[test]
+    fn remap_keeps_other_kind() {
+        let mut keymap = Keymap::default();
+        keymap.bind(5, vec!["KeyW".into(), "ArrowUp".into()]);
+        assert_eq!(keymap.key("GamepadSouth"), Some(5));
+        keymap.bind(5, vec!["GamepadNorth".into()]);
+        assert_eq!(keymap.bindings(5), ["GamepadNorth", "KeyW", "ArrowUp"]);
+        assert_eq!(keymap.key("GamepadSouth"), None);
+        assert!(!keymap.bindings(0xb).contains(&"GamepadNorth".to_string()));
+    }
+
+    #[test]
+    fn keymap_errors() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            keymap.apply("1 = w\nG = a", ""),
+            Err("line 2: invalid keypad key 'G'".into())
+        );
+        assert_eq!(
+            keymap.apply("1 = arrow-up", ""),
+            Err("line 1: invalid key 'arrow-up'".into())
+        );
+        assert_eq!(
+            keymap.apply("[pong", ""),
+            Err("line 1: invalid section '[pong'".into())
+        );
+        assert_eq!(
+            keymap.apply("1 w", ""),
+            Err("line 1: expected 'KEY = CODE...', got '1 w'".into())
+        );
+        assert!("1234".parse::<Keymap>().is_err());
+    }
+}
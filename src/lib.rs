@@ -1,94 +1,32 @@
-mod chip8;
-mod render;
-
-use std::fs;
-
-use chip8::{Chip8, W_HEIGHT, W_WIDTH};
-use render::Render;
-use winit::{
-    dpi::PhysicalSize,
-    event::{ElementState, Event, KeyEvent, WindowEvent},
-    event_loop::EventLoop,
-    keyboard::{Key, NamedKey},
-    window::WindowBuilder,
-};
-
-pub fn run(scaling_factor: usize, rom_path: &str) {
-    let (w_height, w_width) = (
-        (W_HEIGHT * scaling_factor) as u32,
-        (W_WIDTH * scaling_factor) as u32,
-    );
-
-    let event_loop = EventLoop::new().unwrap();
-
-    let window = WindowBuilder::new()
-        .with_title("A fantastic window!")
-        .with_inner_size(winit::dpi::LogicalSize::new(w_width, w_height))
-        .build(&event_loop)
-        .unwrap();
-
-    let mut chip = Chip8::new();
-    let mut render = pollster::block_on(Render::new(window));
-
-    chip.load(&fs::read(rom_path).unwrap());
-
-    let _ = event_loop.run(move |event, elwt| {
-        match event {
-            Event::WindowEvent { event, window_id } if window_id == render.window().id() => {
-                if !render.input(&event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    logical_key: Key::Named(NamedKey::Escape),
-                                    state: ElementState::Pressed,
-                                    ..
-                                },
-                            ..
-                        } => elwt.exit(),
-                        WindowEvent::RedrawRequested => {
-                            // Notify the windowing system that we'll be presenting to the window.
-                            match render.render(chip.pixels()) {
-                                Ok(_) => {}
-                                Err(wgpu::SurfaceError::Lost) => render.resize(*render.size()),
-                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                                Err(e) => eprintln!("Unexpeted errror :{:?}", e),
-                            }
-                        }
-                        WindowEvent::Resized(physical_size) => {
-                            render.resize(physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            let inner_size = render.window().inner_size();
-                            render.resize(PhysicalSize {
-                                width: (scale_factor * f64::from(inner_size.width)) as u32,
-                                height: (scale_factor * f64::from(inner_size.height)) as u32,
-                            });
-                        }
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    logical_key: Key::Character(key),
-                                    state,
-                                    ..
-                                },
-                            ..
-                        } => chip.update_key_states(key.as_str(), state),
-                        _ => (),
-                    }
-                }
-            }
-            Event::AboutToWait => {
-                render.window().request_redraw();
-            }
-
-            _ => (),
-        }
-
-        if chip.step() == chip8::Target::Pixels {
-            render.window().request_redraw();
-        }
-    });
-}
+pub mod assembler;
+pub mod audio;
+pub mod chip8;
+pub mod cli;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod flicker;
+pub mod headless;
+pub mod input;
+pub mod keymap;
+pub mod octo;
+pub mod options;
+pub mod palette;
+pub mod quirks;
+#[cfg(feature = "window")]
+pub mod render;
+pub mod rewind;
+pub mod scheduler;
+pub mod state;
+pub mod timer;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "window")]
+mod window;
+
+pub use error::Chip8Error;
+pub use options::Options;
+pub use quirks::Quirks;
+pub use scheduler::Speed;
+#[cfg(feature = "window")]
+pub use window::run;
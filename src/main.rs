@@ -1,4 +1,220 @@
-fn main() {
-    let scaling_factor = 10;
-    let _ = crab8::run(scaling_factor, "roms/pong2.ch8");
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use crab8::{
+    assembler,
+    audio::{Audio, AudioSink, NullSink, WavSink},
+    cli::{self, Command, USAGE},
+    debugger::{Debugger, Reply},
+    disassembler,
+    headless::Headless,
+    keymap::KEYMAP_FILE,
+    octo,
+    options::config_dir,
+    palette::PALETTE_FILE,
+    Options,
+};
+
+// Octo sources have the .8o extension, anything else is crab8 assembly
+fn is_octo(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "8o")
+}
+
+fn audio_sink(options: &Options) -> Result<Box<dyn AudioSink>, String> {
+    if let Some(path) = &options.wav {
+        let sink = WavSink::create(path)
+            .map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+        return Ok(Box::new(sink));
+    }
+    #[cfg(feature = "cpal")]
+    if !options.headless {
+        match crab8::audio::CpalSink::new() {
+            Ok(sink) => return Ok(Box::new(sink)),
+            Err(e) => eprintln!("warning: sound disabled: {}", e),
+        }
+    }
+    Ok(Box::new(NullSink::default()))
+}
+
+// Run `frames` frames, handing over to the debugger console on stdin while paused
+fn run_headless(headless: &mut Headless, mut frames: usize) -> Result<(), String> {
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        frames -= headless
+            .run_frames(frames)
+            .map_err(|e| format!("crashed: {}", e))?;
+        let Some(debugger) = headless.debugger_mut() else {
+            return Ok(());
+        };
+        if !debugger.is_paused() {
+            return Ok(());
+        }
+        if let Some(stop) = debugger.take_stop() {
+            println!("{} at {:03X}", stop, headless.chip().pc());
+        }
+
+        loop {
+            print!("(crab8) ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match headless.command(&line) {
+                Ok(Reply::Print(text)) => println!("{}", text.trim_end()),
+                Ok(Reply::Resume) => break,
+                Ok(Reply::Quit) => return Ok(()),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+}
+
+fn run(rom: &[u8], options: &Options) -> Result<(), String> {
+    let audio = Audio::new(audio_sink(options)?);
+
+    if options.headless {
+        let mut headless = Headless::with_options(rom, options).map_err(|e| e.to_string())?;
+        headless.set_audio(audio);
+        if options.pause || !options.breakpoints.is_empty() {
+            let mut debugger = Debugger::new(options.breakpoints.clone());
+            if options.pause {
+                debugger.pause();
+            }
+            headless.set_debugger(debugger);
+        }
+        let result = run_headless(&mut headless, options.frames);
+        print!("{}", headless.state());
+        if let Some(path) = &options.screenshot {
+            let palette = options.palettes.select(options.palette.as_deref())?;
+            fs::write(path, palette.ppm(&headless.chip().planes()))
+                .map_err(|e| format!("cannot write '{}': {}", path.display(), e))?;
+        }
+        return result;
+    }
+
+    if options.tui {
+        #[cfg(feature = "tui")]
+        return crab8::tui::run(rom, options, audio);
+        #[cfg(not(feature = "tui"))]
+        return Err("built without terminal support".to_string());
+    }
+
+    #[cfg(feature = "window")]
+    return crab8::run(rom, options, audio);
+    #[cfg(not(feature = "window"))]
+    Err("built without window support, use --headless".to_string())
+}
+
+// Apply the keymap file given with --keymap, or the one of the config directory if any
+fn load_keymap(options: &mut Options, rom: &Path) -> Result<(), String> {
+    let path = match &options.keymap_file {
+        Some(path) => path.clone(),
+        None => match config_dir().map(|dir| dir.join(KEYMAP_FILE)) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        },
+    };
+    let rom = rom
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    options.keymap.load(path, &rom)
+}
+
+// Apply the palette file given with --palettes, or the one of the config
+// directory if any, then check the chosen palette exists
+fn load_palettes(options: &mut Options) -> Result<(), String> {
+    let path = match &options.palette_file {
+        Some(path) => Some(path.clone()),
+        None => config_dir()
+            .map(|dir| dir.join(PALETTE_FILE))
+            .filter(|path| path.exists()),
+    };
+    if let Some(path) = path {
+        options.palettes.load(path)?;
+    }
+    options
+        .palettes
+        .select(options.palette.as_deref())
+        .map(|_| ())
+}
+
+fn main() -> ExitCode {
+    let (rom_path, mut options) = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run { rom, options }) => (rom, *options),
+        Ok(Command::Disassemble { rom }) => {
+            return match fs::read(&rom) {
+                Ok(bytes) => {
+                    print!("{}", disassembler::disassemble(&bytes));
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("error: cannot read ROM '{}': {}", rom.display(), e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Ok(Command::Assemble { source, output }) => {
+            let rom = if is_octo(&source) {
+                octo::compile_file(&source)
+            } else {
+                assembler::assemble_file(&source)
+            };
+            let result = rom.map_err(|e| e.to_string()).and_then(|rom| {
+                fs::write(&output, rom)
+                    .map_err(|e| format!("cannot write '{}': {}", output.display(), e))
+            });
+            return match result {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let level = if options.debug {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Warn
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+
+    let rom = if is_octo(&rom_path) {
+        octo::compile_file(&rom_path).map_err(|e| e.to_string())
+    } else {
+        fs::read(&rom_path).map_err(|e| format!("cannot read ROM '{}': {}", rom_path.display(), e))
+    };
+    let result = rom
+        .and_then(|rom| load_keymap(&mut options, &rom_path).map(|_| rom))
+        .and_then(|rom| load_palettes(&mut options).map(|_| rom))
+        .and_then(|rom| run(&rom, &options));
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
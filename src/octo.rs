@@ -0,0 +1,890 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::assembler::AsmError;
+use crate::chip8::LOAD_START;
+
+// Macro expansions allowed in a program, to stop on recursive macros
+const MAX_EXPANSIONS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: index + 1,
+            })
+        })
+        .collect()
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(s: &str) -> Option<u16> {
+    let digit = s.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(digit, 16).ok()
+}
+
+// Open control structure, waiting for the address of its end
+enum Flow {
+    Loop { start: usize, exits: Vec<usize> },
+    // Address of the jump to patch with the `else` or `end` address
+    Begin { jump: usize },
+    Else { jump: usize },
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    position: usize,
+    // Line of the last token read, for errors
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    // Labels found by the previous pass, for forward references
+    previous: HashMap<String, usize>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    expansions: usize,
+    flow: Vec<Flow>,
+    // `:next` labels waiting for the next instruction
+    next: Vec<String>,
+    // Unknown names are errors only on the final pass
+    strict: bool,
+}
+
+impl Compiler {
+    fn new(source: &str, previous: HashMap<String, usize>, start: usize, strict: bool) -> Self {
+        Self {
+            tokens: tokenize(source),
+            position: 0,
+            line: 0,
+            rom: Vec::new(),
+            here: start,
+            previous,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            flow: Vec::new(),
+            next: Vec::new(),
+            strict,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: "<source>".to_string(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.position += 1;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(self.error(format!("expected '{}', got '{}'", expected, token)));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && parse_register(&name).is_none();
+        if !valid {
+            return Err(self.error(format!("invalid name '{}'", name)));
+        }
+        Ok(name)
+    }
+
+    fn register(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        self.as_register(&token)
+            .ok_or_else(|| self.error(format!("expected a register, got '{}'", token)))
+    }
+
+    fn as_register(&self, token: &str) -> Option<u16> {
+        parse_register(token).or_else(|| self.aliases.get(token).copied())
+    }
+
+    fn lookup(&self, name: &str) -> Result<i64, AsmError> {
+        if name == "HERE" {
+            return Ok(self.here as i64);
+        }
+        if let Some(number) = parse_number(name) {
+            return Ok(number);
+        }
+        if let Some(value) = self.constants.get(name) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(name).or_else(|| self.previous.get(name)) {
+            return Ok(*address as i64);
+        }
+        if self.strict {
+            Err(self.error(format!("undefined name '{}'", name)))
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn value(&mut self, min: i64, max: i64) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        let value = self.lookup(&token)?;
+        if self.strict && !(min..=max).contains(&value) {
+            return Err(self.error(format!("{} is out of range for '{}'", value, token)));
+        }
+        Ok(value as u16)
+    }
+
+    fn address(&mut self) -> Result<u16, AsmError> {
+        Ok(self.value(0, 0xfff)? & 0xfff)
+    }
+
+    fn byte(&mut self) -> Result<u16, AsmError> {
+        Ok(self.value(-128, 0xff)? & 0xff)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        let offset = self.here - LOAD_START;
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+    }
+
+    fn instruction(&mut self, opcode: u16) {
+        for name in std::mem::take(&mut self.next) {
+            self.labels.insert(name, self.here + 1);
+        }
+        self.emit(&opcode.to_be_bytes());
+    }
+
+    // Write the address of a jump emitted earlier at `at`
+    fn patch(&mut self, at: usize, target: usize) {
+        let opcode = 0x1000 | (target as u16 & 0xfff);
+        let offset = at - LOAD_START;
+        self.rom[offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+    }
+
+    // `:calc` expression between braces, evaluated right to left as in Octo
+    fn calc(&mut self) -> Result<i64, AsmError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<i64, AsmError> {
+        let left = self.term()?;
+        let operator = match self.peek() {
+            Some(
+                operator @ ("+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "<" | ">"
+                | "<=" | ">=" | "==" | "!=" | "min" | "max"),
+            ) => operator.to_string(),
+            _ => return Ok(left),
+        };
+        self.next()?;
+        let right = self.expression()?;
+        let value = match operator.as_str() {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err(self.error("division by zero")),
+            "/" => left / right,
+            "%" => left % right,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "<" => (left < right) as i64,
+            ">" => (left > right) as i64,
+            "<=" => (left <= right) as i64,
+            ">=" => (left >= right) as i64,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "min" => left.min(right),
+            _ => left.max(right),
+        };
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "-" => Ok(-self.term()?),
+            "~" => Ok(!self.term()?),
+            "!" => Ok((self.term()? == 0) as i64),
+            _ => self.lookup(&token),
+        }
+    }
+
+    // Instructions skipping the next one when the condition equals `skip_when`
+    fn condition(&mut self, skip_when: bool) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        match operator.as_str() {
+            "key" | "-key" => {
+                let pressed = (operator == "key") == skip_when;
+                self.instruction(if pressed { 0xe09e } else { 0xe0a1 } | x << 8);
+            }
+            "==" | "!=" => {
+                let equal = (operator == "==") == skip_when;
+                let operand = self.next()?;
+                if let Some(y) = self.as_register(&operand) {
+                    self.instruction(if equal { 0x5000 } else { 0x9000 } | x << 8 | y << 4);
+                } else {
+                    self.position -= 1;
+                    let nn = self.byte()?;
+                    self.instruction(if equal { 0x3000 } else { 0x4000 } | x << 8 | nn);
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                // VF := operand, then VF is the no borrow flag of either
+                // VX - VF (VX >= operand) or VF - VX (operand >= VX)
+                let operand = self.next()?;
+                if let Some(y) = self.as_register(&operand) {
+                    self.instruction(0x8f00 | y << 4);
+                } else {
+                    self.position -= 1;
+                    let nn = self.byte()?;
+                    self.instruction(0x6f00 | nn);
+                }
+                let (subtraction, holds_when) = match operator.as_str() {
+                    ">=" => (0x8f07, 1),
+                    "<" => (0x8f07, 0),
+                    "<=" => (0x8f05, 1),
+                    _ => (0x8f05, 0),
+                };
+                self.instruction(subtraction | x << 4);
+                let skip_on = if skip_when {
+                    holds_when
+                } else {
+                    1 - holds_when
+                };
+                self.instruction(0x3f00 | skip_on);
+            }
+            _ => return Err(self.error(format!("unknown comparison '{}'", operator))),
+        }
+        Ok(())
+    }
+
+    fn assignment(&mut self, x: u16) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = self.as_register(&operand);
+        let opcode = match (operator.as_str(), operand.as_str(), y) {
+            (":=", _, Some(y)) => 0x8000 | y << 4,
+            (":=", "random", _) => 0xc000 | self.byte()?,
+            (":=", "key", _) => 0xf00a,
+            (":=", "delay", _) => 0xf007,
+            ("+=", _, Some(y)) => 0x8004 | y << 4,
+            ("-=", _, Some(y)) => 0x8005 | y << 4,
+            ("=-", _, Some(y)) => 0x8007 | y << 4,
+            ("|=", _, Some(y)) => 0x8001 | y << 4,
+            ("&=", _, Some(y)) => 0x8002 | y << 4,
+            ("^=", _, Some(y)) => 0x8003 | y << 4,
+            (">>=", _, Some(y)) => 0x8006 | y << 4,
+            ("<<=", _, Some(y)) => 0x800e | y << 4,
+            (":=" | "+=" | "-=", _, None) => {
+                self.position -= 1;
+                let nn = self.byte()?;
+                match operator.as_str() {
+                    ":=" => 0x6000 | nn,
+                    "+=" => 0x7000 | nn,
+                    _ => 0x7000 | (nn.wrapping_neg() & 0xff),
+                }
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "invalid operation 'v{:x} {} {}'",
+                    x, operator, operand
+                )))
+            }
+        };
+        self.instruction(opcode | x << 8);
+        Ok(())
+    }
+
+    fn i_assignment(&mut self) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match (operator.as_str(), self.peek()) {
+            ("+=", _) => {
+                let x = self.register()?;
+                self.instruction(0xf01e | x << 8);
+            }
+            (":=", Some("hex")) => {
+                self.next()?;
+                let x = self.register()?;
+                self.instruction(0xf029 | x << 8);
+            }
+            (":=", Some("bighex")) => {
+                self.next()?;
+                let x = self.register()?;
+                self.instruction(0xf030 | x << 8);
+            }
+            (":=", Some("long")) => {
+                self.next()?;
+                let address = self.value(0, 0xffff)?;
+                self.instruction(0xf000);
+                self.emit(&address.to_be_bytes());
+            }
+            (":=", _) => {
+                let address = self.address()?;
+                self.instruction(0xa000 | address);
+            }
+            _ => return Err(self.error(format!("invalid operation 'i {}'", operator))),
+        }
+        Ok(())
+    }
+
+    // `save vx` or `save vx - vy`, same for load
+    fn register_range(&mut self, single: u16, range: u16) -> Result<(), AsmError> {
+        let x = self.register()?;
+        if self.peek() == Some("-") {
+            self.next()?;
+            let y = self.register()?;
+            self.instruction(range | x << 8 | y << 4);
+        } else {
+            self.instruction(single | x << 8);
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        while self.peek() != Some("{") {
+            params.push(self.name()?);
+        }
+        self.next()?;
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self
+                .tokens
+                .get(self.position)
+                .cloned()
+                .ok_or_else(|| self.error(format!("macro {} is missing '}}'", name)))?;
+            self.position += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, (params, body));
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error("too many macro expansions"));
+        }
+        let (params, body) = self.macros[name].clone();
+        let mut args = HashMap::new();
+        for param in &params {
+            args.insert(param.clone(), self.next()?);
+        }
+        let line = self.line;
+        let expanded: Vec<Token> = body
+            .into_iter()
+            .map(|token| Token {
+                text: args.get(&token.text).cloned().unwrap_or(token.text),
+                line,
+            })
+            .collect();
+        self.tokens.splice(self.position..self.position, expanded);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if self.labels.insert(name.clone(), self.here).is_some() {
+                    return Err(self.error(format!("'{}' is defined twice", name)));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let value = self.lookup(&token)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name, x);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()?
+                } else {
+                    let token = self.next()?;
+                    self.lookup(&token)?
+                };
+                self.emit(&[value as u8]);
+            }
+            ":org" => {
+                let address = self.value(LOAD_START as i64, 0xffff)? as usize;
+                self.here = address.max(LOAD_START);
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let address = self.address()?;
+                self.instruction(0x2000 | address);
+            }
+            ":unpack" => {
+                let high = self.value(0, 0xf)?;
+                let address = self.address()?;
+                self.instruction(0x6000 | high << 4 | address >> 8);
+                self.instruction(0x6100 | (address & 0xff));
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.next.push(name);
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.instruction(0x00ee),
+            "clear" => self.instruction(0x00e0),
+            "hires" => self.instruction(0x00ff),
+            "lores" => self.instruction(0x00fe),
+            "exit" => self.instruction(0x00fd),
+            "scroll-down" => {
+                let n = self.value(0, 0xf)?;
+                self.instruction(0x00c0 | n);
+            }
+            "scroll-up" => {
+                let n = self.value(0, 0xf)?;
+                self.instruction(0x00d0 | n);
+            }
+            "scroll-right" => self.instruction(0x00fb),
+            "scroll-left" => self.instruction(0x00fc),
+            "audio" => self.instruction(0xf002),
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(0xf033 | x << 8);
+            }
+            "save" => self.register_range(0xf055, 0x5002)?,
+            "load" => self.register_range(0xf065, 0x5003)?,
+            "saveflags" => {
+                let x = self.register()?;
+                self.instruction(0xf075 | x << 8);
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.instruction(0xf085 | x << 8);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.value(0, 0xf)?;
+                self.instruction(0xd000 | x << 8 | y << 4 | n);
+            }
+            "jump" => {
+                let address = self.address()?;
+                self.instruction(0x1000 | address);
+            }
+            "jump0" => {
+                let address = self.address()?;
+                self.instruction(0xb000 | address);
+            }
+            "native" => {
+                let address = self.address()?;
+                self.instruction(address);
+            }
+            "plane" => {
+                let n = self.value(0, 3)?;
+                self.instruction(0xf001 | n << 8);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let opcode = match token.as_str() {
+                    "delay" => 0xf015,
+                    "buzzer" => 0xf018,
+                    _ => 0xf03a,
+                };
+                self.instruction(opcode | x << 8);
+            }
+            "i" => self.i_assignment()?,
+            "if" => {
+                // Peek past the condition, at most 3 tokens, for `then` or `begin`
+                let begin = self.tokens[self.position..]
+                    .iter()
+                    .take(4)
+                    .find(|t| t.text == "then" || t.text == "begin")
+                    .is_some_and(|t| t.text == "begin");
+                self.condition(begin)?;
+                let token = self.next()?;
+                if token != "then" && token != "begin" {
+                    return Err(self.error(format!("expected 'then' or 'begin', got '{}'", token)));
+                }
+                // Skips are 2 bytes, so `then` cannot skip a 4 byte instruction
+                let next: Vec<&str> = self.tokens[self.position..]
+                    .iter()
+                    .take(3)
+                    .map(|t| t.text.as_str())
+                    .collect();
+                if !begin && next == ["i", ":=", "long"] {
+                    return Err(self.error("'then' cannot skip 'i := long', use 'begin' instead"));
+                }
+                if begin {
+                    self.flow.push(Flow::Begin { jump: self.here });
+                    self.instruction(0x1000);
+                }
+            }
+            "else" => match self.flow.pop() {
+                Some(Flow::Begin { jump }) => {
+                    self.flow.push(Flow::Else { jump: self.here });
+                    self.instruction(0x1000);
+                    self.patch(jump, self.here);
+                }
+                _ => return Err(self.error("'else' without 'begin'")),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::Begin { jump } | Flow::Else { jump }) => self.patch(jump, self.here),
+                _ => return Err(self.error("'end' without 'begin'")),
+            },
+            "loop" => self.flow.push(Flow::Loop {
+                start: self.here,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                self.condition(true)?;
+                let jump = self.here;
+                self.instruction(0x1000);
+                match self.flow.iter_mut().rev().find_map(|flow| match flow {
+                    Flow::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(jump),
+                    None => return Err(self.error("'while' outside of a loop")),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop { start, exits }) => {
+                    self.instruction(0x1000 | start as u16);
+                    for jump in exits {
+                        self.patch(jump, self.here);
+                    }
+                }
+                _ => return Err(self.error("'again' without 'loop'")),
+            },
+            _ if self.macros.contains_key(&token) => self.expand_macro(&token)?,
+            _ => {
+                if let Some(x) = self.as_register(&token) {
+                    self.assignment(x)?;
+                } else if let Some(number) = parse_number(&token) {
+                    // Sprite data and other raw bytes
+                    if self.strict && !(-128..=0xff).contains(&number) {
+                        return Err(self.error(format!("{} does not fit in a byte", number)));
+                    }
+                    self.emit(&[number as u8]);
+                } else if self.constants.contains_key(&token) {
+                    let value = self.constants[&token];
+                    self.emit(&[value as u8]);
+                } else {
+                    // Bare label names call the subroutine
+                    self.position -= 1;
+                    let address = self.address()?;
+                    self.instruction(0x2000 | address);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile(mut self) -> Result<Self, AsmError> {
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+        if !self.flow.is_empty() {
+            return Err(self.error("missing 'end' or 'again'"));
+        }
+        Ok(self)
+    }
+}
+
+// Compile Octo source into a ROM loaded at 0x200
+pub fn compile(source: &str) -> Result<Vec<u8>, AsmError> {
+    // Programs not starting with `: main` begin with a jump to it
+    let first = Compiler::new(source, HashMap::new(), LOAD_START, false).compile()?;
+    let main = *first
+        .labels
+        .get("main")
+        .ok_or_else(|| first.error("missing ': main' label"))?;
+    let start = if main == LOAD_START {
+        LOAD_START
+    } else {
+        LOAD_START + 2
+    };
+
+    // Resolve forward references with the labels of a lenient pass
+    let layout = Compiler::new(source, HashMap::new(), start, false).compile()?;
+    let mut compiler = Compiler::new(source, layout.labels, start, true).compile()?;
+    if start != LOAD_START {
+        let main = compiler.labels["main"] as u16;
+        compiler.rom.resize(compiler.rom.len().max(2), 0);
+        compiler.rom[..2].copy_from_slice(&(0x1000 | main).to_be_bytes());
+    }
+    Ok(compiler.rom)
+}
+
+pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: file.clone(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    compile(&source).map_err(|e| AsmError { file, ..e })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn statements() {
+        let source = "
+            : main  # comment
+                clear
+                v0 := 5  v1 := v0  v2 += 3  v2 -= 1  v3 =- v2  v4 >>= v4
+                i := sprite  i += v1  i := long 0x1234  i := hex v0
+                delay := v0  v5 := key  v6 := random 0xF0
+                sprite v0 v1 4
+                save v3  load v1 - v2
+                return
+            : sprite
+                0b10011001 0xFF
+        ";
+        assert_eq!(
+            compile(source).unwrap(),
+            [
+                0x00, 0xe0, 0x60, 0x05, 0x81, 0x00, 0x72, 0x03, 0x72, 0xff, 0x83, 0x27, 0x84, 0x46,
+                0xa2, 0x26, 0xf1, 0x1e, 0xf0, 0x00, 0x12, 0x34, 0xf0, 0x29, 0xf0, 0x15, 0xf5, 0x0a,
+                0xc6, 0xf0, 0xd0, 0x14, 0xf3, 0x55, 0x51, 0x23, 0x00, 0xee, 0x99, 0xff
+            ]
+        );
+    }
+
+    #[test]
+    fn jump_to_main_and_calls() {
+        let source = "
+            : draw  sprite v0 v0 1  ;
+            : main  draw  loop again
+        ";
+        assert_eq!(
+            compile(source).unwrap(),
+            [0x12, 0x06, 0xd0, 0x01, 0x00, 0xee, 0x22, 0x02, 0x12, 0x08]
+        );
+    }
+
+    #[test]
+    fn constants_macros_and_calc() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            :calc DOUBLE { SPEED * 2 + 1 }
+            :macro twice op { op op }
+            : main
+                x := SPEED
+                twice clear
+                x += DOUBLE
+                :byte { 10 - 2 - 1 }
+                :unpack 0xA main
+        ";
+        // Right to left: 10 - (2 - 1) = 9 and 3 * (2 + 1) = 9
+        assert_eq!(
+            compile(source).unwrap(),
+            [0x64, 0x03, 0x00, 0xe0, 0x00, 0xe0, 0x74, 0x09, 0x09, 0x60, 0xa2, 0x61, 0x00]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 != v1 begin
+                    v2 := 1
+                else
+                    v2 := 2
+                end
+                loop
+                    v3 += 1
+                    while v3 < 10
+                again
+        ";
+        assert_eq!(
+            compile(source).unwrap(),
+            [
+                0x40, 0x01, 0x61, 0x02, // if then
+                0x90, 0x10, 0x12, 0x0c, 0x62, 0x01, 0x12, 0x0e, 0x62,
+                0x02, // if begin else end
+                0x73, 0x01, 0x6f, 0x0a, 0x8f, 0x37, 0x3f, 0x00, 0x12, 0x1a, 0x12,
+                0x0e, // loop
+            ]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        // VF := operand, VF := VX - VF or VF - VX, then skip on the borrow flag
+        assert_eq!(
+            compile(": main if v0 < 6 then v1 := 1 if v0 >= v2 begin end").unwrap(),
+            [
+                0x6f, 0x06, 0x8f, 0x07, 0x3f, 0x01, 0x61, 0x01, // <
+                0x8f, 0x20, 0x8f, 0x07, 0x3f, 0x01, 0x12, 0x10, // >=
+            ]
+        );
+        assert_eq!(
+            compile(": main if v3 > 4 then clear if v3 <= 4 then clear").unwrap(),
+            [
+                0x6f, 0x04, 0x8f, 0x35, 0x3f, 0x01, 0x00, 0xe0, // >
+                0x6f, 0x04, 0x8f, 0x35, 0x3f, 0x00, 0x00, 0xe0, // <=
+            ]
+        );
+    }
+
+    #[test]
+    fn comparisons_run() {
+        // Count the comparisons holding for v0 = 5 in vA, and the others in vB
+        let source = "
+            : main
+                v0 := 5
+                if v0 < 6 then vA += 1
+                if v0 <= 5 then vA += 1
+                if v0 > 4 then vA += 1
+                if v0 >= 5 then vA += 1
+                if v0 < 5 then vB += 1
+                if v0 > 5 then vB += 1
+                if v0 >= 6 then vB += 1
+                if v0 <= 4 then vB += 1
+                loop again
+        ";
+        let mut headless = Headless::new(&compile(source).unwrap()).unwrap();
+        headless.run_cycles(100).unwrap();
+        assert_eq!(headless.chip().v_registers()[0xa..0xc], [4, 0]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("clear"), "<source>:1: missing ': main' label");
+        assert_eq!(
+            error(": main\njump nowhere"),
+            "<source>:2: undefined name 'nowhere'"
+        );
+        assert_eq!(
+            error(": main\nv0 := 256"),
+            "<source>:2: 256 is out of range for '256'"
+        );
+        assert_eq!(
+            error(": main\nloop"),
+            "<source>:2: missing 'end' or 'again'"
+        );
+        assert_eq!(error(": main\nelse"), "<source>:2: 'else' without 'begin'");
+        assert_eq!(
+            error(": main\nif v0 ~ 1 then"),
+            "<source>:2: unknown comparison '~'"
+        );
+        assert_eq!(
+            error(
+                ": main
+if v0 == 1 clear v1 := 2 loop again"
+            ),
+            "<source>:2: expected 'then' or 'begin', got 'clear'"
+        );
+        assert_eq!(
+            error(
+                ": main
+if v0 == 1 v2 := 3"
+            ),
+            "<source>:2: expected 'then' or 'begin', got 'v2'"
+        );
+        assert_eq!(
+            error(": main\nif v0 == 1 then i := long main"),
+            "<source>:2: 'then' cannot skip 'i := long', use 'begin' instead"
+        );
+        assert_eq!(
+            error(": main\n: main"),
+            "<source>:2: 'main' is defined twice"
+        );
+    }
+}
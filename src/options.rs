@@ -0,0 +1,160 @@
+use std::{env, path::PathBuf};
+
+use crate::debugger::Breakpoint;
+use crate::flicker::FrameBlend;
+use crate::keymap::Keymap;
+use crate::palette::Palettes;
+use crate::quirks::Quirks;
+use crate::scheduler::Speed;
+
+pub const DEFAULT_SCALE: usize = 10;
+pub const DEFAULT_FRAMES: usize = 600;
+
+// CRT post-processing, each effect from 0 (off) to 1
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effects {
+    // Darkening between pixel rows
+    pub scanlines: f32,
+    // Part of the previous frame kept on pixels turned off, hiding flicker
+    pub phosphor: f32,
+    // Glow of lit pixels over their neighbors
+    pub bloom: f32,
+    // Barrel distortion of the screen
+    pub curvature: f32,
+}
+
+impl Effects {
+    pub fn crt() -> Self {
+        Self {
+            scanlines: 0.35,
+            phosphor: 0.6,
+            bloom: 0.25,
+            curvature: 0.1,
+        }
+    }
+
+    pub fn is_off(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// Parse `none`, `crt` or `EFFECT=AMOUNT,...` (e.g. `scanlines=0.5,phosphor=0.8`)
+impl std::str::FromStr for Effects {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "off" => return Ok(Self::default()),
+            "crt" => return Ok(Self::crt()),
+            _ => {}
+        }
+        let mut effects = Self::default();
+        for setting in s.split(',') {
+            let (name, amount) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("invalid effect '{}', expected EFFECT=AMOUNT", setting))?;
+            let amount = amount
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|amount| (0.0..=1.0).contains(amount))
+                .ok_or_else(|| format!("invalid amount '{}', expected 0 to 1", amount))?;
+            let effect = match name.trim() {
+                "scanlines" => &mut effects.scanlines,
+                "phosphor" => &mut effects.phosphor,
+                "bloom" => &mut effects.bloom,
+                "curvature" => &mut effects.curvature,
+                name => return Err(format!("unknown effect '{}'", name)),
+            };
+            *effect = amount;
+        }
+        Ok(effects)
+    }
+}
+
+// `$XDG_CONFIG_HOME/crab8`, or `~/.config/crab8`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("crab8"))
+}
+
+// Settings shared by the windowed and headless frontends
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub scale: usize,
+    pub speed: Speed,
+    pub quirks: Quirks,
+    pub keymap: Keymap,
+    // Keymap file applied over `keymap`, KEYMAP_FILE of the config directory unless set
+    pub keymap_file: Option<PathBuf>,
+    // Palette name or colors, the file default or classic unless set
+    pub palette: Option<String>,
+    // Palette file applied over the built-in palettes, PALETTE_FILE of the
+    // config directory unless set
+    pub palette_file: Option<PathBuf>,
+    pub palettes: Palettes,
+    pub effects: Effects,
+    // Combination of the last two frames hiding sprite flicker
+    pub blend: FrameBlend,
+    // Run for a fixed number of frames without window, then print the machine state
+    pub headless: bool,
+    // Draw in the terminal instead of a window
+    pub tui: bool,
+    pub frames: usize,
+    pub debug: bool,
+    // Record sound into a WAV file
+    pub wav: Option<PathBuf>,
+    // Save the last headless frame as a PPM image
+    pub screenshot: Option<PathBuf>,
+    // Base path of quick save slots, the ROM path unless set
+    pub saves: Option<PathBuf>,
+    pub breakpoints: Vec<Breakpoint>,
+    // Start paused in the debugger
+    pub pause: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scale: DEFAULT_SCALE,
+            speed: Speed::default(),
+            quirks: Quirks::default(),
+            keymap: Keymap::default(),
+            keymap_file: None,
+            palette: None,
+            palette_file: None,
+            palettes: Palettes::default(),
+            effects: Effects::default(),
+            blend: FrameBlend::default(),
+            headless: false,
+            tui: false,
+            frames: DEFAULT_FRAMES,
+            debug: false,
+            wav: None,
+            screenshot: None,
+            saves: None,
+            breakpoints: Vec::new(),
+            pause: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_effects() {
+        assert_eq!("crt".parse(), Ok(Effects::crt()));
+        assert!("none".parse::<Effects>().unwrap().is_off());
+        let effects: Effects = "scanlines=0.5, phosphor=1".parse().unwrap();
+        assert_eq!((effects.scanlines, effects.phosphor), (0.5, 1.0));
+        assert_eq!(effects.bloom, 0.0);
+        assert!("bloom=2".parse::<Effects>().is_err());
+        assert!("glow=0.5".parse::<Effects>().is_err());
+        assert!("bloom".parse::<Effects>().is_err());
+    }
+}
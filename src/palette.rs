@@ -0,0 +1,313 @@
+use std::{fs, path::Path, str::FromStr};
+
+use crate::flicker::BLENDED;
+
+// File read from the configuration directory when no --palettes is given
+pub const PALETTE_FILE: &str = "palettes.conf";
+
+pub type Color = [u8; 3];
+
+// Foreground (pixel on) and background (pixel off) colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub foreground: Color,
+    pub background: Color,
+    // XO-CHIP pixels lit on the second plane only, then on both planes
+    pub plane2: Color,
+    pub overlap: Color,
+}
+
+const fn palette(foreground: u32, background: u32, plane2: u32, overlap: u32) -> Palette {
+    const fn color(value: u32) -> Color {
+        [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+    Palette {
+        foreground: color(foreground),
+        background: color(background),
+        plane2: color(plane2),
+        overlap: color(overlap),
+    }
+}
+
+// Palettes available by name, the first one being the default, followed by
+// the Octo themes under their Octo names
+const BUILTIN: [(&str, Palette); 9] = [
+    ("classic", palette(0xffffff, 0x000000, 0xaaaaaa, 0x555555)),
+    ("green", palette(0x33ff66, 0x0a1a0f, 0x1e8c3c, 0x8cffaa)),
+    ("amber", palette(0xffb000, 0x1a0f00, 0x996a00, 0xffd780)),
+    ("octo", palette(0xffcc00, 0x996600, 0xff6600, 0x662200)),
+    ("lcd", palette(0x3d8026, 0xf9ffb3, 0xabcc47, 0x00131a)),
+    ("hotdog", palette(0xff0000, 0x000000, 0xffff00, 0xffffff)),
+    ("gray", palette(0x000000, 0xaaaaaa, 0xffffff, 0x666666)),
+    ("cga0", palette(0x00ffff, 0x000000, 0xff00ff, 0xffffff)),
+    ("cga1", palette(0xffff00, 0x000000, 0xff0000, 0xffffff)),
+];
+
+impl Palette {
+    // Colors indexed by the planes a pixel is lit on
+    pub fn colors(&self) -> [Color; 4] {
+        [self.background, self.foreground, self.plane2, self.overlap]
+    }
+
+    // Color of a palette index, halfway to the previous frame one for
+    // blended pixels (see `flicker::AntiFlicker`)
+    pub fn color(&self, index: u8) -> Color {
+        let colors = self.colors();
+        let color = colors[index as usize & 3];
+        if index & BLENDED == 0 {
+            return color;
+        }
+        let previous = colors[(index as usize >> 2) & 3];
+        [0, 1, 2].map(|i| (color[i] as u16 + previous[i] as u16).div_ceil(2) as u8)
+    }
+
+    // RGB rows of palette indices, as returned by `Chip8::planes`
+    pub fn image(&self, planes: &[Vec<u8>]) -> Vec<u8> {
+        planes
+            .iter()
+            .flatten()
+            .flat_map(|&index| self.color(index))
+            .collect()
+    }
+
+    // Binary PPM file of palette indices, readable by most image tools
+    pub fn ppm(&self, planes: &[Vec<u8>]) -> Vec<u8> {
+        let (width, height) = (planes.first().map_or(0, Vec::len), planes.len());
+        let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        ppm.extend(self.image(planes));
+        ppm
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        BUILTIN[0].1
+    }
+}
+
+// Parse `RRGGBB,RRGGBB[,RRGGBB,RRGGBB]` (foreground, background, then XO-CHIP colors)
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = s
+            .split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<_>, _>>()?;
+        let default = Self::default();
+        match colors[..] {
+            [foreground, background] => Ok(Self {
+                foreground,
+                background,
+                ..default
+            }),
+            [foreground, background, plane2, overlap] => Ok(Self {
+                foreground,
+                background,
+                plane2,
+                overlap,
+            }),
+            _ => Err(format!(
+                "invalid palette '{}', expected 2 or 4 RRGGBB colors",
+                s
+            )),
+        }
+    }
+}
+
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.trim().trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("invalid color '{}', expected RRGGBB", s))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+// Named palettes, built-in ones followed by those of a palette file, and the
+// one picked by default
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palettes {
+    named: Vec<(String, Palette)>,
+    // Palette name or colors set with `palette = ...` in a palette file
+    default: Option<String>,
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self {
+            named: BUILTIN
+                .iter()
+                .map(|&(name, palette)| (name.to_string(), palette))
+                .collect(),
+            default: None,
+        }
+    }
+}
+
+impl Palettes {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.named.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Palette> {
+        self.named
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // Palette named `s`, or given as colors
+    pub fn find(&self, s: &str) -> Result<Palette, String> {
+        match self.get(s) {
+            Some(palette) => Ok(palette),
+            None if s.contains(',') => s.parse(),
+            None => Err(format!(
+                "unknown palette '{}', expected one of {} or colors",
+                s,
+                self.names().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    // Palette chosen on the command line, else the file default, else classic
+    pub fn select(&self, choice: Option<&str>) -> Result<Palette, String> {
+        match choice.or(self.default.as_deref()) {
+            Some(s) => self.find(s),
+            None => Ok(Palette::default()),
+        }
+    }
+
+    // Palette after `palette` in the list, with its name, wrapping around
+    pub fn next(&self, palette: Palette) -> (&str, Palette) {
+        let index = self
+            .named
+            .iter()
+            .position(|&(_, p)| p == palette)
+            .map_or(0, |index| (index + 1) % self.named.len());
+        let (name, palette) = &self.named[index];
+        (name, *palette)
+    }
+
+    // Apply a palette file, made of `NAME = COLORS` lines defining palettes
+    // (replacing built-in ones of the same name) and an optional
+    // `palette = NAME` line picking the default one. Comments start with `#`.
+    pub fn apply(&mut self, config: &str) -> Result<(), String> {
+        for (index, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| error(format!("expected 'NAME = COLORS', got '{}'", line)))?;
+            if name == "palette" {
+                self.default = Some(value.to_string());
+                continue;
+            }
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+                return Err(error(format!("invalid palette name '{}'", name)));
+            }
+            let palette = value.parse().map_err(error)?;
+            match self.named.iter_mut().find(|(n, _)| n == name) {
+                Some((_, p)) => *p = palette,
+                None => self.named.push((name.to_string(), palette)),
+            }
+        }
+        // Checked once every palette is defined, wherever the line is
+        if let Some(default) = &self.default {
+            self.find(default)?;
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read palettes '{}': {}", path.display(), e))?;
+        self.apply(&config)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_palette() {
+        let palette: Palette = "ff0000,000000".parse().unwrap();
+        assert_eq!(palette.colors()[..2], [[0, 0, 0], [0xff, 0, 0]]);
+        assert_eq!(palette.overlap, Palette::default().overlap);
+        let palette: Palette = "ffffff,000000,ff0000,00ff00".parse().unwrap();
+        assert_eq!(palette.colors()[2..], [[0xff, 0, 0], [0, 0xff, 0]]);
+        assert!("ffffff,000000,ff0000".parse::<Palette>().is_err());
+        assert!("ffffff".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn named_palettes() {
+        let palettes = Palettes::default();
+        assert_eq!(palettes.select(None), Ok(Palette::default()));
+        let amber = palettes.find("amber").unwrap();
+        assert_eq!(amber.foreground, [0xff, 0xb0, 0x00]);
+        assert_eq!(
+            palettes.find("ff0000,000000").unwrap().foreground,
+            [0xff, 0, 0]
+        );
+        assert!(palettes
+            .find("mauve")
+            .unwrap_err()
+            .contains("classic, green"));
+        assert_eq!(
+            palettes.next(Palette::default()),
+            ("green", palettes.get("green").unwrap())
+        );
+        assert_eq!(palettes.next(palettes.get("cga1").unwrap()).0, "classic");
+        let custom: Palette = "123456,000000".parse().unwrap();
+        assert_eq!(palettes.next(custom).0, "classic");
+    }
+
+    #[test]
+    fn palette_file() {
+        let config = "
+            # Custom themes
+            palette = mine
+            mine = 112233,445566
+            amber = ffffff,000000
+        ";
+        let mut palettes = Palettes::default();
+        palettes.apply(config).unwrap();
+        assert_eq!(
+            palettes.select(None).unwrap().foreground,
+            [0x11, 0x22, 0x33]
+        );
+        assert_eq!(
+            palettes.select(Some("amber")).unwrap().foreground,
+            [0xff; 3]
+        );
+        assert_eq!(palettes.names().last(), Some("mine"));
+
+        let mut palettes = Palettes::default();
+        assert_eq!(
+            palettes.apply("mine = 112233"),
+            Err("line 1: invalid palette '112233', expected 2 or 4 RRGGBB colors".into())
+        );
+        assert_eq!(
+            palettes.apply("my theme = 112233,445566"),
+            Err("line 1: invalid palette name 'my theme'".into())
+        );
+        assert!(palettes.apply("palette = mauve").is_err());
+    }
+
+    #[test]
+    fn ppm_export() {
+        let palette = Palettes::default().get("hotdog").unwrap();
+        let ppm = palette.ppm(&[vec![0, 1], vec![2, 3]]);
+        assert_eq!(&ppm[..11], b"P6\n2 2\n255\n");
+        assert_eq!(&ppm[11..], [0, 0, 0, 255, 0, 0, 255, 255, 0, 255, 255, 255]);
+        assert_eq!(palette.color(BLENDED | 1 << 2), [128, 0, 0]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::str::FromStr;
+
+// How FX55/FX65 update I after saving or loading registers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadStore {
+    // I += X + 1
+    Increment,
+    // I += X
+    IncrementX,
+    Unchanged,
+}
+
+// Behaviors differing between CHIP-8 interpreters over the years
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // BNNN jumps to XNN + VX, instead of NNN + V0
+    pub jump_with_vx: bool,
+    pub load_store: LoadStore,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // DXYN waits for the next vertical blank, limiting drawing to once a frame
+    pub display_wait: bool,
+    // Sprites are clipped at the screen edges, instead of wrapping around
+    pub clip_sprites: bool,
+    // FX0A returns once the key is released, instead of as soon as it is pressed
+    pub key_release: bool,
+}
+
+impl Quirks {
+    pub const PRESETS: [&'static str; 4] = ["vip", "chip48", "schip", "modern"];
+
+    // Original interpreter of the COSMAC VIP
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_with_vx: false,
+            load_store: LoadStore::Increment,
+            vf_reset: true,
+            display_wait: true,
+            clip_sprites: true,
+            key_release: true,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            jump_with_vx: true,
+            load_store: LoadStore::IncrementX,
+            vf_reset: false,
+            display_wait: false,
+            clip_sprites: true,
+            key_release: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1
+    pub fn superchip() -> Self {
+        Self {
+            load_store: LoadStore::Unchanged,
+            ..Self::chip48()
+        }
+    }
+
+    // Octo and most recent interpreters
+    pub fn modern() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_with_vx: false,
+            load_store: LoadStore::Increment,
+            vf_reset: false,
+            display_wait: false,
+            clip_sprites: false,
+            key_release: true,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::modern()
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "chip8" => Ok(Self::cosmac_vip()),
+            "chip48" => Ok(Self::chip48()),
+            "schip" | "superchip" => Ok(Self::superchip()),
+            "modern" | "octo" | "xochip" => Ok(Self::modern()),
+            _ => Err(format!(
+                "unknown quirks profile '{}', expected one of {}",
+                s,
+                Self::PRESETS.join(", ")
+            )),
+        }
+    }
+}
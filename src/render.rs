@@ -1,11 +1,483 @@
 use winit::{event::WindowEvent, window::Window};
 
+use crate::options::Effects;
+use crate::palette::{Color, Palette};
+
+// Both passes draw a full target triangle. The phosphor pass turns palette
+// indices into colors at the CHIP-8 resolution, fading pixels that went off
+// from the previous frame. The present pass draws that frame in the
+// letterboxed area of the target, adding the CRT effects, with nearest
+// neighbor scaling.
+const SHADER: &str = "
+struct Uniforms {
+    colors: array<vec4<f32>, 4>,
+    // Letterboxed screen area in the target, in pixels: x, y, width, height
+    area: vec4<f32>,
+    // Scanlines, phosphor, bloom and curvature, from 0 to 1
+    effects: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+@group(0) @binding(1) var indices: texture_2d<u32>;
+@group(0) @binding(2) var previous: texture_2d<f32>;
+@group(0) @binding(3) var frame: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_phosphor(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let index = textureLoad(indices, pixel, 0).r;
+    var color = uniforms.colors[index & 3u];
+    // Pixels blended with the previous frame, see `flicker::AntiFlicker`
+    if (index & 0x80u) != 0u {
+        color = mix(color, uniforms.colors[(index >> 2u) & 3u], 0.5);
+    }
+    // Lit pixels show at once, others fade to the background
+    if index != 0u {
+        return color;
+    }
+    return mix(color, textureLoad(previous, pixel, 0), uniforms.effects.y);
+}
+
+fn load(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(frame));
+    let pixel = vec2<i32>(clamp(uv * size, vec2<f32>(0.0), size - 1.0));
+    return textureLoad(frame, pixel, 0);
+}
+
+@fragment
+fn fs_present(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(frame));
+    let centered = (position.xy - uniforms.area.xy) / uniforms.area.zw * 2.0 - 1.0;
+    // Barrel distortion, corners falling outside of the screen
+    let uv = centered * (1.0 + 0.25 * uniforms.effects.w * dot(centered, centered)) * 0.5 + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    var color = load(uv).rgb;
+    // Light of the surrounding pixels bleeding over
+    if uniforms.effects.z > 0.0 {
+        var glow = vec3<f32>(0.0);
+        for (var y = -2; y <= 2; y += 1) {
+            for (var x = -2; x <= 2; x += 1) {
+                glow += load(uv + vec2<f32>(f32(x), f32(y)) / size).rgb;
+            }
+        }
+        color += glow / 25.0 * uniforms.effects.z;
+    }
+    // Darker edges on each row of pixels
+    let row = fract(uv.y * size.y);
+    color *= 1.0 - uniforms.effects.x * (1.0 - sin(row * 3.14159265));
+    return vec4<f32>(color, 1.0);
+}
+";
+
+// Uniforms layout: 4 colors, the screen area then the effects
+const UNIFORMS_SIZE: u64 = 6 * 16;
+
+// Phosphor frames, keeping the colors fading out between frames
+const FRAME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Largest area of the screen aspect ratio fitting in the target, centered,
+// as x, y, width and height
+pub fn letterbox(target: (u32, u32), screen: (u32, u32)) -> (u32, u32, u32, u32) {
+    let scale = f64::min(
+        target.0 as f64 / screen.0 as f64,
+        target.1 as f64 / screen.1 as f64,
+    );
+    let width = ((screen.0 as f64 * scale).round() as u32).clamp(1, target.0.max(1));
+    let height = ((screen.1 as f64 * scale).round() as u32).clamp(1, target.1.max(1));
+    (
+        (target.0 - width.min(target.0)) / 2,
+        (target.1 - height.min(target.1)) / 2,
+        width,
+        height,
+    )
+}
+
+fn texture_entry(binding: u32, sample_type: wgpu::TextureSampleType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn texture(
+    device: &wgpu::Device,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Screen Texture"),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+// Textures at the CHIP-8 resolution
+struct Screen {
+    // Palette indices
+    indices: wgpu::Texture,
+    // Frames written alternately, each pass reading the other one as previous
+    frames: [wgpu::TextureView; 2],
+    phosphor_groups: [wgpu::BindGroup; 2],
+    present_groups: [wgpu::BindGroup; 2],
+    current: usize,
+    size: (u32, u32),
+}
+
+// Render pipelines drawing the CHIP-8 screen into textures of a given format
+struct Pipeline {
+    phosphor: wgpu::RenderPipeline,
+    present: wgpu::RenderPipeline,
+    phosphor_layout: wgpu::BindGroupLayout,
+    present_layout: wgpu::BindGroupLayout,
+    uniforms: wgpu::Buffer,
+    // Created on first draw and on resolution changes
+    screen: Option<Screen>,
+    format: wgpu::TextureFormat,
+    palette: Palette,
+    effects: Effects,
+}
+
+impl Pipeline {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        palette: Palette,
+        effects: Effects,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Screen Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let uniforms_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+        let phosphor_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Phosphor Bind Group Layout"),
+            entries: &[
+                uniforms_entry,
+                texture_entry(1, wgpu::TextureSampleType::Uint),
+                texture_entry(2, float),
+            ],
+        });
+        let present_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Present Bind Group Layout"),
+            entries: &[uniforms_entry, texture_entry(3, float)],
+        });
+
+        let pipeline = |label, layout: &wgpu::BindGroupLayout, entry_point, format| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let phosphor = pipeline(
+            "Phosphor Pipeline",
+            &phosphor_layout,
+            "fs_phosphor",
+            FRAME_FORMAT,
+        );
+        let present = pipeline("Present Pipeline", &present_layout, "fs_present", format);
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screen Uniforms"),
+            size: UNIFORMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            phosphor,
+            present,
+            phosphor_layout,
+            present_layout,
+            uniforms,
+            screen: None,
+            format,
+            palette,
+            effects,
+        }
+    }
+
+    fn resize_screen(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if self.screen.as_ref().map(|screen| screen.size) == Some(size) {
+            return;
+        }
+        let indices = texture(
+            device,
+            size,
+            wgpu::TextureFormat::R8Uint,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        let indices_view = indices.create_view(&wgpu::TextureViewDescriptor::default());
+        let frames = [(); 2].map(|_| {
+            texture(
+                device,
+                size,
+                FRAME_FORMAT,
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let uniforms = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.uniforms.as_entire_binding(),
+        };
+        let phosphor_groups = [0, 1].map(|current| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Phosphor Bind Group"),
+                layout: &self.phosphor_layout,
+                entries: &[
+                    uniforms.clone(),
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&indices_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&frames[1 - current]),
+                    },
+                ],
+            })
+        });
+        let present_groups = [0, 1].map(|current| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Present Bind Group"),
+                layout: &self.present_layout,
+                entries: &[
+                    uniforms.clone(),
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&frames[current]),
+                    },
+                ],
+            })
+        });
+        self.screen = Some(Screen {
+            indices,
+            frames,
+            phosphor_groups,
+            present_groups,
+            current: 0,
+            size,
+        });
+    }
+
+    // Color components for the shader output, linear for sRGB targets
+    fn color(&self, color: Color) -> [f32; 4] {
+        let srgb = self.format.is_srgb();
+        let [r, g, b] = color.map(|c| {
+            let c = c as f32 / 255.0;
+            if !srgb {
+                c
+            } else if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        [r, g, b, 1.0]
+    }
+
+    // Draw palette indices, as returned by `Chip8::planes`, over the whole `view`
+    fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        target: (u32, u32),
+        data: &[Vec<u8>],
+    ) {
+        if data.first().is_none_or(Vec::is_empty) {
+            return;
+        }
+        let size = (data[0].len() as u32, data.len() as u32);
+        let area = letterbox(target, size);
+
+        let effects = self.effects;
+        let mut uniforms: Vec<f32> = self
+            .palette
+            .colors()
+            .iter()
+            .flat_map(|&color| self.color(color))
+            .collect();
+        uniforms.extend([area.0, area.1, area.2, area.3].map(|v| v as f32));
+        uniforms.extend([
+            effects.scanlines,
+            effects.phosphor,
+            effects.bloom,
+            effects.curvature,
+        ]);
+        let bytes: Vec<u8> = uniforms.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        queue.write_buffer(&self.uniforms, 0, &bytes);
+
+        self.resize_screen(device, size);
+        let screen = self.screen.as_mut().unwrap();
+        let indices: Vec<u8> = data.iter().flatten().copied().collect();
+        queue.write_texture(
+            screen.indices.as_image_copy(),
+            &indices,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.0),
+                rows_per_image: Some(size.1),
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        // Buffer storing commands before being send to the GPU
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let current = screen.current;
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Phosphor Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &screen.frames[current],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.phosphor);
+            pass.set_bind_group(0, &screen.phosphor_groups[current], &[]);
+            pass.draw(0..3, 0..1);
+        }
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Present Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Letterbox bars
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.present);
+            pass.set_bind_group(0, &screen.present_groups[current], &[]);
+            pass.set_viewport(
+                area.0 as f32,
+                area.1 as f32,
+                area.2 as f32,
+                area.3 as f32,
+                0.0,
+                1.0,
+            );
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        screen.current = 1 - current;
+    }
+}
+
+fn instance() -> wgpu::Instance {
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+    })
+}
+
+async fn device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web we'll have to disable some.
+                limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits())
+                },
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
 pub struct Render {
     surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
+    pipeline: Pipeline,
     // The window must be declared after the surface so
     // it gets dropped after it as the surface contains
     // unsafe references to the window's resources.
@@ -14,22 +486,16 @@ pub struct Render {
 
 impl Render {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, palette: Palette, effects: Effects) -> Result<Self, String> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-            flags: wgpu::InstanceFlags::default(),
-            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-        });
+        let instance = instance();
         // # Safety
         //
         // The surface needs to live as long as the window that created it.
         // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }.map_err(|e| e.to_string())?;
 
         // GPU handle to our actual graphics card
         let adapter = instance
@@ -39,30 +505,11 @@ impl Render {
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+            .ok_or("no suitable graphics adapter found")?;
+        let (device, queue) = device(&adapter).await?;
 
+        // Colors are converted to linear for sRGB surfaces, see `Pipeline::color`
         let surface_caps = surface.get_capabilities(&adapter);
-        // Shader code in this tutorial assumes an sRGB surface texture. Using a different
-        // one will result all the colors coming out darker. If you want to support non
-        // sRGB surfaces, you'll need to account for that when drawing to the frame.
         let surface_format = surface_caps
             .formats
             .iter()
@@ -70,24 +517,27 @@ impl Render {
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::COPY_DST,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            width: size.width.max(1),
+            height: size.height.max(1),
+            // Presented on vertical blank
+            present_mode: wgpu::PresentMode::AutoVsync,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
         surface.configure(&device, &config);
+        let pipeline = Pipeline::new(&device, surface_format, palette, effects);
 
-        Self {
+        Ok(Self {
             window,
             surface,
             device,
             queue,
             config,
             size,
-        }
+            pipeline,
+        })
     }
 
     pub fn window(&self) -> &Window {
@@ -107,58 +557,231 @@ impl Render {
         }
     }
 
+    pub fn effects(&self) -> Effects {
+        self.pipeline.effects
+    }
+
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.pipeline.effects = effects;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.pipeline.palette = palette;
+    }
+
     pub fn input(&mut self, _event: &WindowEvent) -> bool {
         // Return flag wheather an event has been fully processed
         false
     }
 
-    pub fn render(&mut self, data: Vec<Vec<bool>>) -> Result<(), wgpu::SurfaceError> {
+    // Draw palette indices, as returned by `Chip8::planes`
+    pub fn render(&mut self, data: Vec<Vec<u8>>) -> Result<(), wgpu::SurfaceError> {
         let frame = self.surface.get_current_texture()?;
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.pipeline.draw(
+            &self.device,
+            &self.queue,
+            &view,
+            (self.config.width, self.config.height),
+            &data,
+        );
+        frame.present();
+        Ok(())
+    }
+}
 
-        // Buffer storing commands before being send to the GPU
-        let encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+// Render target without window, read back into memory
+pub struct Offscreen {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    texture: wgpu::Texture,
+    size: (u32, u32),
+    pipeline: Pipeline,
+}
 
-        // Scale pixels to match texture
-        let v_scaling = (self.config.height as usize) / data.len();
-        let h_scaling = (self.config.width as usize) / data[0].len();
-        let data: Vec<u8> = data
-            .iter()
-            .flat_map(|row| {
-                let u8_row: Vec<u8> = row
-                    .iter()
-                    .flat_map(|&p| {
-                        if p {
-                            vec![255u8; 4 * h_scaling]
-                        } else {
-                            vec![0u8; 4 * h_scaling]
-                        }
-                    })
-                    .collect();
-                vec![u8_row; v_scaling].into_iter().flatten()
+impl Offscreen {
+    // Any adapter does, including software ones, as nothing is presented
+    pub async fn new(width: u32, height: u32, palette: Palette) -> Result<Self, String> {
+        let adapter = instance()
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
             })
-            .collect();
+            .await
+            .ok_or("no suitable graphics adapter found")?;
+        let (device, queue) = device(&adapter).await?;
 
-        self.queue.write_texture(
-            frame.texture.as_image_copy(),
-            &data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * self.config.width),
-                rows_per_image: Some(self.config.height),
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let pipeline = Pipeline::new(&device, format, palette, Effects::default());
+
+        Ok(Self {
+            device,
+            queue,
+            texture,
+            size: (width, height),
+            pipeline,
+        })
+    }
+
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.pipeline.effects = effects;
+    }
+
+    // Draw palette indices and return the target as RGBA rows
+    pub fn render(&mut self, data: Vec<Vec<u8>>) -> Vec<u8> {
+        let view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.pipeline
+            .draw(&self.device, &self.queue, &view, self.size, &data);
+
+        // Buffer rows are padded to the copy alignment
+        let (width, height) = self.size;
+        let row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded = row.div_ceil(align) * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Buffer"),
+            size: (padded * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded),
+                    rows_per_image: Some(height),
+                },
             },
             wgpu::Extent3d {
-                width: self.config.width,
-                height: self.config.height,
+                width,
+                height,
                 depth_or_array_layers: 1,
             },
         );
         self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
 
-        Ok(())
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        self.device.poll(wgpu::Maintain::Wait);
+        let mapped = slice.get_mapped_range();
+        let pixels = mapped
+            .chunks(padded as usize)
+            .flat_map(|line| &line[..row as usize])
+            .copied()
+            .collect();
+        drop(mapped);
+        buffer.unmap();
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterboxing() {
+        assert_eq!(letterbox((640, 320), (64, 32)), (0, 0, 640, 320));
+        assert_eq!(letterbox((640, 480), (64, 32)), (0, 80, 640, 320));
+        assert_eq!(letterbox((300, 400), (128, 64)), (0, 125, 300, 150));
+        assert_eq!(letterbox((1000, 100), (64, 32)), (400, 0, 200, 100));
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn offscreen_rendering() {
+        let palette = Palette::default();
+        let mut offscreen = pollster::block_on(Offscreen::new(8, 8, palette)).unwrap();
+        // 4x2 screen with every palette index on the top row, letterboxed
+        // into 8x4 between 2 rows of bars
+        let data = vec![vec![0, 1, 2, 3], vec![1, 1, 1, 1]];
+        let pixels = offscreen.render(data);
+        let pixel = |x: usize, y: usize| -> [u8; 3] {
+            let i = 4 * (y * 8 + x);
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+        let colors = palette.colors();
+        assert_eq!(pixel(0, 0), [0, 0, 0]);
+        assert_eq!(pixel(0, 2), colors[0]);
+        assert_eq!(pixel(3, 3), colors[1]);
+        assert_eq!(pixel(4, 2), colors[2]);
+        assert_eq!(pixel(7, 3), colors[3]);
+        assert_eq!(pixel(7, 4), colors[1]);
+        assert_eq!(pixel(7, 7), [0, 0, 0]);
+    }
+
+    fn offscreen(width: u32, height: u32, effects: Effects) -> Offscreen {
+        let palette = Palette::default();
+        let mut offscreen = pollster::block_on(Offscreen::new(width, height, palette)).unwrap();
+        offscreen.set_effects(effects);
+        offscreen
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn phosphor_decay() {
+        let effects = Effects {
+            phosphor: 0.5,
+            ..Effects::default()
+        };
+        let mut offscreen = offscreen(1, 1, effects);
+        // Lit at once, then halving on every frame turned off
+        assert_eq!(offscreen.render(vec![vec![1]])[0], 0xff);
+        let fading: Vec<u8> = (0..3).map(|_| offscreen.render(vec![vec![0]])[0]).collect();
+        for (value, expected) in fading.into_iter().zip([128, 64, 32]) {
+            assert!(value.abs_diff(expected) <= 1, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn blended_pixels() {
+        let mut offscreen = pollster::block_on(Offscreen::new(1, 1, Palette::default())).unwrap();
+        let index = crate::flicker::BLENDED | 1 << 2;
+        let pixel = offscreen.render(vec![vec![index]]);
+        assert!(pixel[0].abs_diff(128) <= 1, "{}", pixel[0]);
+        // Nothing to draw before the first frame
+        offscreen.render(Vec::new());
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn scanlines() {
+        let effects = Effects {
+            scanlines: 1.0,
+            ..Effects::default()
+        };
+        let mut offscreen = offscreen(8, 8, effects);
+        // Rows of a screen pixel get brighter toward their middle
+        let pixels = offscreen.render(vec![vec![1]]);
+        let row = |y: usize| pixels[4 * 8 * y];
+        assert!(row(0) < row(2) && row(2) < row(4));
+        assert!(row(4) > 0xf0 && row(7) < row(4));
     }
 }
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::timer::TIMER_FREQUENCY;
+
+// Ten seconds of history when recording every frame
+pub const DEFAULT_REWIND_FRAMES: usize = 10 * TIMER_FREQUENCY as usize;
+
+// Ring buffer of machine snapshots, the oldest being dropped once full
+pub struct Rewind {
+    // Run-length encoded snapshots, most recent at the back
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+    // Frames between two snapshots
+    interval: usize,
+    frames: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_FRAMES, 1)
+    }
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+    }
+
+    // To be called after each frame, keeps a snapshot every `interval` frames
+    pub fn record(&mut self, chip: &Chip8) {
+        if self.frames.is_multiple_of(self.interval) {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+            if self.capacity > 0 {
+                self.snapshots.push_back(compress(&chip.snapshot()));
+            }
+        }
+        self.frames += 1;
+    }
+
+    // Go back to the most recent snapshot and forget it. The oldest one is
+    // kept so that holding the rewind key stops there.
+    pub fn step_back(&mut self, chip: &mut Chip8) -> bool {
+        let snapshot = match self.snapshots.len() {
+            0 => return false,
+            1 => self.snapshots[0].clone(),
+            _ => self.snapshots.pop_back().unwrap(),
+        };
+        self.frames = 0;
+        chip.restore(&decompress(&snapshot)).is_ok()
+    }
+}
+
+// Snapshots are mostly made of zeroes (unused memory, blank pixels), stored
+// as a 0 followed by the run length
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = data.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        out.push(byte);
+        if byte == 0 {
+            let mut run = 1u8;
+            while run < u8::MAX && bytes.next_if_eq(&0).is_some() {
+                run += 1;
+            }
+            out.push(run);
+        }
+    }
+    out
+}
+
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = data.iter().copied();
+    while let Some(byte) = bytes.next() {
+        if byte == 0 {
+            let run = bytes.next().unwrap_or(1);
+            out.resize(out.len() + run as usize, 0);
+        } else {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_roundtrip() {
+        let data = [&[1, 0, 0, 2][..], &[0; 600], &[3, 0]].concat();
+        let compressed = compress(&data);
+        assert!(compressed.len() < 16);
+        assert_eq!(decompress(&compressed), data);
+        let snapshot = Chip8::default().snapshot();
+        assert_eq!(decompress(&compress(&snapshot)), snapshot);
+    }
+
+    #[test]
+    fn rewind_steps_back() {
+        // V0 += 1 forever
+        let mut chip = Chip8::default();
+        chip.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = Rewind::new(3, 2);
+        for _ in 0..10 {
+            chip.step().unwrap();
+            chip.step().unwrap();
+            rewind.record(&chip);
+        }
+        // Snapshots taken every other frame, with V0 at 5, 7 and 9, remain
+        assert_eq!(rewind.len(), 3);
+        assert!(rewind.step_back(&mut chip));
+        assert_eq!(chip.v_registers()[0], 9);
+        assert!(rewind.step_back(&mut chip));
+        assert_eq!(chip.v_registers()[0], 7);
+        // The oldest snapshot stays
+        assert!(rewind.step_back(&mut chip));
+        assert!(rewind.step_back(&mut chip));
+        assert_eq!(chip.v_registers()[0], 5);
+        assert_eq!(rewind.len(), 1);
+        rewind.clear();
+        assert!(!rewind.step_back(&mut chip));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{fmt, str::FromStr};
+
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::chip8::{Chip8, Instruction, Target};
+use crate::debugger::Debugger;
+use crate::error::Chip8Error;
+use crate::timer::TIMER_FREQUENCY;
+
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+// Microseconds of CPU time in one 60 Hz frame
+const FRAME_MICROS: f64 = 1_000_000.0 / TIMER_FREQUENCY as f64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    InstructionsPerFrame(u32),
+    Hz(u32),
+    // Per-instruction timing of the original COSMAC VIP interpreter
+    Cosmac,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::InstructionsPerFrame(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::InstructionsPerFrame(n) => write!(f, "{}ipf", n),
+            Speed::Hz(n) => write!(f, "{}hz", n),
+            Speed::Cosmac => write!(f, "cosmac"),
+        }
+    }
+}
+
+// Parse `cosmac`, `<n>hz` or `<n>ipf` (a bare number is instructions per frame)
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let parse = |n: &str| {
+            n.parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid speed '{}'", s))
+        };
+
+        if s == "cosmac" {
+            Ok(Speed::Cosmac)
+        } else if let Some(n) = s.strip_suffix("hz") {
+            parse(n).map(Speed::Hz)
+        } else if let Some(n) = s.strip_suffix("ipf") {
+            parse(n).map(Speed::InstructionsPerFrame)
+        } else {
+            parse(&s).map(Speed::InstructionsPerFrame)
+        }
+    }
+}
+
+// Outcome of one scheduled frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub instructions: usize,
+    // Whether the display changed during the frame
+    pub drawn: bool,
+    // Whether the program exited, remaining instructions of the frame are skipped
+    pub exited: bool,
+    // Whether the debugger paused the machine, remaining instructions are skipped too
+    pub paused: bool,
+}
+
+impl Frame {
+    fn step(
+        &mut self,
+        chip: &mut Chip8,
+        debugger: Option<&mut Debugger>,
+    ) -> Result<(), Chip8Error> {
+        if self.exited || self.paused {
+            return Ok(());
+        }
+        let target = match debugger {
+            Some(debugger) => {
+                let target = debugger.step(chip)?;
+                self.paused = debugger.is_paused();
+                match target {
+                    Some(target) => target,
+                    None => return Ok(()),
+                }
+            }
+            None => chip.step()?,
+        };
+        match target {
+            Target::Pixels => self.drawn = true,
+            Target::Exit => self.exited = true,
+            Target::Memory => (),
+        }
+        self.instructions += 1;
+        Ok(())
+    }
+}
+
+// Decide how many instructions run in each 60 Hz frame
+pub struct Scheduler {
+    speed: Speed,
+    // Instructions (or microseconds for Cosmac) left over from previous frames
+    budget: f64,
+    // Seeded so that Cosmac timings are reproducible from run to run
+    rng: StdRng,
+}
+
+impl Scheduler {
+    pub fn new(speed: Speed) -> Self {
+        Self {
+            speed,
+            budget: 0.0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.budget = 0.0;
+    }
+
+    // Execute one frame worth of instructions
+    pub fn run_frame(&mut self, chip: &mut Chip8) -> Result<Frame, Chip8Error> {
+        self.run_frame_with(chip, None)
+    }
+
+    // Same as `run_frame`, executing instructions through the debugger
+    pub fn run_frame_with(
+        &mut self,
+        chip: &mut Chip8,
+        mut debugger: Option<&mut Debugger>,
+    ) -> Result<Frame, Chip8Error> {
+        let mut frame = Frame::default();
+        match self.speed {
+            Speed::InstructionsPerFrame(n) => {
+                for _ in 0..n {
+                    frame.step(chip, debugger.as_deref_mut())?;
+                }
+            }
+            Speed::Hz(hz) => {
+                self.budget += hz as f64 / TIMER_FREQUENCY as f64;
+                while self.budget >= 1.0 {
+                    frame.step(chip, debugger.as_deref_mut())?;
+                    self.budget -= 1.0;
+                }
+            }
+            Speed::Cosmac => {
+                self.budget += FRAME_MICROS;
+                while self.budget > 0.0 {
+                    let interval = chip
+                        .peek()
+                        .map_or(0.0, |i| cosmac_interval(&i).sample(&mut self.rng));
+                    frame.step(chip, debugger.as_deref_mut())?;
+                    self.budget -= interval.max(1.0);
+                }
+            }
+        }
+        Ok(frame)
+    }
+}
+
+// Expected duration (in microseconds) of each instruction on the COSMAC VIP
+fn cosmac_interval(instruction: &Instruction) -> Normal<f64> {
+    match instruction {
+        Instruction(0, 0, 0xe, 0) => Normal::new(109.0, 0.0).unwrap(),
+        Instruction(0, 0, 0xe, 0xe)
+        | Instruction(1, ..)
+        | Instruction(2, ..)
+        | Instruction(0xb, ..) => Normal::new(105.0, 5.0).unwrap(),
+        Instruction(3, ..) | Instruction(4, ..) | Instruction(0xa, ..) => {
+            Normal::new(55.0, 9.0).unwrap()
+        }
+        Instruction(5, ..) | Instruction(9, ..) | Instruction(0xe, ..) => {
+            Normal::new(73.0, 0.0).unwrap()
+        }
+        Instruction(6, ..) => Normal::new(27.0, 0.0).unwrap(),
+        Instruction(7, ..)
+        | Instruction(0xf, .., 0, 7)
+        | Instruction(0xf, .., 1, 5)
+        | Instruction(0xf, .., 1, 8) => Normal::new(45.0, 0.0).unwrap(),
+        Instruction(8, ..) => Normal::new(200.0, 0.0).unwrap(),
+        Instruction(0xc, ..) => Normal::new(164.0, 0.0).unwrap(),
+        Instruction(0xf, .., 0, 0xa) => Normal::new(0.0, 0.0).unwrap(),
+        Instruction(0xf, .., 1, 0xe) => Normal::new(86.0, 14.0).unwrap(),
+        Instruction(0xf, .., 2, 9) => Normal::new(91.0, 0.0).unwrap(),
+        Instruction(0xf, .., 3, 3) => Normal::new(927.0, 545.0).unwrap(),
+        Instruction(0xf, .., 5 | 6, 5) => Normal::new(605.0, 477.0).unwrap(),
+        Instruction(0xd, ..) => Normal::new(22734.0, 4634.0).unwrap(),
+        // Unknown opcodes are reported when executed
+        _ => Normal::new(0.0, 0.0).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::Breakpoint;
+
+    #[test]
+    fn parse_speed() {
+        assert_eq!("cosmac".parse(), Ok(Speed::Cosmac));
+        assert_eq!("700Hz".parse(), Ok(Speed::Hz(700)));
+        assert_eq!("15ipf".parse(), Ok(Speed::InstructionsPerFrame(15)));
+        assert_eq!("8".parse(), Ok(Speed::InstructionsPerFrame(8)));
+        assert!("0".parse::<Speed>().is_err());
+        assert!("fast".parse::<Speed>().is_err());
+    }
+
+    // ROM made of `V0 := 0` instructions only, so PC counts executed steps
+    fn counting_chip() -> Chip8 {
+        let mut chip = Chip8::default();
+        chip.load(&[0x60, 0x00].repeat(1000)).unwrap();
+        chip
+    }
+
+    fn steps(chip: &Chip8) -> usize {
+        (chip.pc() - 0x200) / 2
+    }
+
+    #[test]
+    fn instructions_per_frame() {
+        let mut chip = counting_chip();
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerFrame(3));
+        scheduler.run_frame(&mut chip).unwrap();
+        scheduler.run_frame(&mut chip).unwrap();
+        assert_eq!(steps(&chip), 6);
+    }
+
+    #[test]
+    fn hz_budget_is_carried_over() {
+        let mut chip = counting_chip();
+        // 1.5 instructions per frame
+        let mut scheduler = Scheduler::new(Speed::Hz(90));
+        scheduler.run_frame(&mut chip).unwrap();
+        assert_eq!(steps(&chip), 1);
+        scheduler.run_frame(&mut chip).unwrap();
+        assert_eq!(steps(&chip), 3);
+    }
+
+    #[test]
+    fn debugger_pauses_frame() {
+        let mut chip = counting_chip();
+        let mut debugger = Debugger::new(vec![Breakpoint::Pc(0x206)]);
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerFrame(10));
+        let frame = scheduler
+            .run_frame_with(&mut chip, Some(&mut debugger))
+            .unwrap();
+        assert!(frame.paused);
+        assert_eq!((frame.instructions, steps(&chip)), (3, 3));
+        let frame = scheduler
+            .run_frame_with(&mut chip, Some(&mut debugger))
+            .unwrap();
+        assert_eq!(frame.instructions, 0);
+    }
+
+    #[test]
+    fn cosmac_timing() {
+        let mut chip = counting_chip();
+        let mut scheduler = Scheduler::new(Speed::Cosmac);
+        scheduler.run_frame(&mut chip).unwrap();
+        // 6XNN takes 27us, a frame lasts 16666us
+        assert_eq!(steps(&chip), 618);
+    }
+}
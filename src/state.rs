@@ -0,0 +1,215 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+
+// Save states start with the magic and a little endian u16 version, bumped
+// whenever the layout written by `Chip8::snapshot` changes
+pub const STATE_MAGIC: &[u8; 4] = b"C8ST";
+pub const STATE_VERSION: u16 = 3;
+
+pub const SLOT_COUNT: u8 = 10;
+
+// Little endian encoder for save states
+#[derive(Default)]
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        let mut writer = Self::default();
+        writer.bytes(STATE_MAGIC);
+        writer.u16(STATE_VERSION);
+        writer
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// Decoder matching `StateWriter`, failing on truncated or foreign data
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+fn invalid(reason: &str) -> Chip8Error {
+    Chip8Error::InvalidState {
+        reason: reason.to_string(),
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Result<Self, Chip8Error> {
+        let mut reader = Self { bytes };
+        if reader.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(Chip8Error::InvalidState {
+                reason: format!(
+                    "version {} is not supported, expected {}",
+                    version, STATE_VERSION
+                ),
+            });
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        if self.bytes.len() < len {
+            return Err(invalid("truncated data"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("corrupted data")),
+        }
+    }
+
+    // Make sure nothing is left unread
+    pub(crate) fn finish(self) -> Result<(), Chip8Error> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("trailing data"))
+        }
+    }
+}
+
+// Numbered quick save files, `<base>.s0` to `<base>.s9`
+pub struct SaveSlots {
+    base: PathBuf,
+    slot: u8,
+}
+
+impl SaveSlots {
+    pub fn new<P: AsRef<Path>>(base: P) -> Self {
+        Self {
+            base: base.as_ref().to_path_buf(),
+            slot: 0,
+        }
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    pub fn next(&mut self) {
+        self.slot = (self.slot + 1) % SLOT_COUNT;
+    }
+
+    pub fn previous(&mut self) {
+        self.slot = (self.slot + SLOT_COUNT - 1) % SLOT_COUNT;
+    }
+
+    pub fn path(&self) -> PathBuf {
+        let mut path = self.base.clone().into_os_string();
+        path.push(format!(".s{}", self.slot));
+        path.into()
+    }
+
+    pub fn save(&self, chip: &Chip8) -> Result<(), String> {
+        let path = self.path();
+        fs::write(&path, chip.snapshot())
+            .map_err(|e| format!("cannot write '{}': {}", path.display(), e))
+    }
+
+    pub fn load(&self, chip: &mut Chip8) -> Result<(), String> {
+        let path = self.path();
+        let state =
+            fs::read(&path).map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+        chip.restore(&state)
+            .map_err(|e| format!("cannot load '{}': {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_errors() {
+        assert!(StateReader::new(b"C8").is_err());
+        assert!(StateReader::new(b"ROM!\x01\x00").is_err());
+        assert_eq!(
+            StateReader::new(b"C8ST\x04\x00").err(),
+            Some(Chip8Error::InvalidState {
+                reason: "version 4 is not supported, expected 3".into()
+            })
+        );
+        let mut reader = StateReader::new(b"C8ST\x03\x00\x02").unwrap();
+        assert!(reader.bool().is_err());
+        assert!(reader.u8().is_err());
+    }
+
+    #[test]
+    fn slot_files() {
+        let dir = std::env::temp_dir().join(format!("crab8-slots-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut slots = SaveSlots::new(dir.join("pong.ch8"));
+        slots.previous();
+        assert_eq!(slots.path(), dir.join("pong.ch8.s9"));
+
+        // V0 := 5
+        let mut chip = Chip8::default();
+        chip.load(&[0x60, 0x05]).unwrap();
+        chip.step().unwrap();
+        slots.save(&chip).unwrap();
+
+        let mut other = Chip8::default();
+        assert!(other.restore(&[]).is_err());
+        slots.load(&mut other).unwrap();
+        assert_eq!(other.v_registers()[0], 5);
+        slots.next();
+        assert!(slots.load(&mut other).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
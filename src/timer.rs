@@ -0,0 +1,149 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+// Delay and sound timers count down at 60 Hz, whatever the CPU speed
+pub const TIMER_FREQUENCY: u32 = 60;
+pub const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / TIMER_FREQUENCY as u64);
+
+// Source of time, elapsed since an arbitrary origin
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+// Simulated clock only moving forward when told to. Clones share the same time.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+// Convert the time elapsed on a clock into a number of 60 Hz timer ticks
+pub struct Ticker<C: Clock> {
+    clock: C,
+    last: Duration,
+}
+
+impl<C: Clock> Ticker<C> {
+    pub fn new(clock: C) -> Self {
+        let last = clock.now();
+        Self { clock, last }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    // Number of ticks since the previous call. The remainder is carried over
+    // so no time is lost between calls.
+    pub fn ticks(&mut self) -> u32 {
+        let elapsed = self.clock.now().saturating_sub(self.last);
+        let ticks = (elapsed.as_nanos() / TIMER_PERIOD.as_nanos()) as u32;
+        self.last += TIMER_PERIOD * ticks;
+        ticks
+    }
+
+    // Time left before the next tick
+    pub fn until_next(&self) -> Duration {
+        (self.last + TIMER_PERIOD).saturating_sub(self.clock.now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn one_second_is_sixty_ticks() {
+        let clock = ManualClock::new();
+        let mut ticker = Ticker::new(clock.clone());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(ticker.ticks(), 60);
+        assert_eq!(ticker.ticks(), 0);
+    }
+
+    #[test]
+    fn remainder_is_carried_over() {
+        let clock = ManualClock::new();
+        let mut ticker = Ticker::new(clock.clone());
+
+        clock.advance(TIMER_PERIOD / 2);
+        assert_eq!(ticker.ticks(), 0);
+        clock.advance(TIMER_PERIOD / 2);
+        assert_eq!(ticker.ticks(), 1);
+        clock.advance(TIMER_PERIOD * 3 + TIMER_PERIOD / 2);
+        assert_eq!(ticker.ticks(), 3);
+        assert_eq!(ticker.until_next(), TIMER_PERIOD / 2);
+    }
+
+    #[test]
+    fn delay_timer_busy_wait_ends() {
+        // V0 := 3; delay := V0; loop: V1 := delay; if V1 != 0 jump loop; jump self
+        let rom = [
+            0x60, 0x03, 0xf0, 0x15, 0xf1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0a,
+        ];
+        let mut chip = Chip8::default();
+        chip.load(&rom).unwrap();
+
+        let clock = ManualClock::new();
+        let mut ticker = Ticker::new(clock.clone());
+        for _ in 0..4 {
+            for _ in 0..10 {
+                chip.step().unwrap();
+            }
+            clock.advance(TIMER_PERIOD);
+            for _ in 0..ticker.ticks() {
+                chip.tick_timers();
+            }
+        }
+        for _ in 0..10 {
+            chip.step().unwrap();
+        }
+
+        assert_eq!(chip.delay_timer(), 0);
+        assert_eq!(chip.pc(), 0x20a);
+    }
+}
@@ -0,0 +1,296 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{self, Color},
+    terminal,
+};
+
+use crate::audio::Audio;
+use crate::chip8::Chip8;
+use crate::flicker::{AntiFlicker, FrameBlend};
+use crate::headless::MachineState;
+use crate::keymap;
+use crate::options::Options;
+use crate::palette::Palette;
+use crate::scheduler::Scheduler;
+use crate::timer::{SystemClock, Ticker};
+
+// Frames executed at most on a single wake up
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+// Frames a key stays down after being pressed, long enough for the terminal
+// key repeat to kick in (usually after 250 to 600 ms)
+const PRESS_HOLD_FRAMES: u32 = 40;
+// Frames a key stays down after a repeat, repeats coming at 25 to 40 Hz
+const REPEAT_HOLD_FRAMES: u32 = 6;
+
+// Terminals only report key presses, repeated while a key is held, so keys
+// are released when their repeats stop coming
+#[derive(Debug, Default)]
+pub struct KeyHold {
+    // Frames left before release, by keypad key
+    held: [u32; 16],
+}
+
+impl KeyHold {
+    // Returns whether the key was up
+    pub fn press(&mut self, key: u8) -> bool {
+        let held = &mut self.held[key as usize & 0xf];
+        let was_up = *held == 0;
+        *held = if was_up {
+            PRESS_HOLD_FRAMES
+        } else {
+            REPEAT_HOLD_FRAMES
+        };
+        was_up
+    }
+
+    // Count a frame down, returning the keys to release
+    pub fn frame(&mut self) -> Vec<u8> {
+        let mut released = Vec::new();
+        for (key, held) in self.held.iter_mut().enumerate() {
+            if *held == 1 {
+                released.push(key as u8);
+            }
+            *held = held.saturating_sub(1);
+        }
+        released
+    }
+}
+
+// Keymap code of a terminal key. Terminals report characters rather than
+// physical keys, so letters and digits are taken as their QWERTY position.
+pub fn key_code(code: KeyCode) -> Option<String> {
+    let code = match code {
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char(c) => return keymap::parse_code(&c.to_string()).ok(),
+        KeyCode::Up => "ArrowUp",
+        KeyCode::Down => "ArrowDown",
+        KeyCode::Left => "ArrowLeft",
+        KeyCode::Right => "ArrowRight",
+        KeyCode::Enter => "Enter",
+        KeyCode::Tab => "Tab",
+        KeyCode::Backspace => "Backspace",
+        _ => return None,
+    };
+    Some(code.to_string())
+}
+
+fn color(color: [u8; 3]) -> Color {
+    let [r, g, b] = color;
+    Color::Rgb { r, g, b }
+}
+
+// Draw palette indices, as returned by `Chip8::planes`, from the top left
+// corner of the terminal. Each cell is an upper half block, showing a pixel
+// in its foreground color and the one below in its background color.
+pub fn draw<W: Write>(out: &mut W, planes: &[Vec<u8>], palette: &Palette) -> io::Result<()> {
+    for (row, pair) in planes.chunks(2).enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16))?;
+        let mut last = None;
+        for (x, &top) in pair[0].iter().enumerate() {
+            let bottom = pair.get(1).map_or(0, |line| line[x]);
+            let colors = (palette.color(top), palette.color(bottom));
+            if last != Some(colors) {
+                queue!(
+                    out,
+                    style::SetColors(style::Colors::new(color(colors.0), color(colors.1)))
+                )?;
+                last = Some(colors);
+            }
+            queue!(out, style::Print('▀'))?;
+        }
+    }
+    queue!(out, style::ResetColor)?;
+    out.flush()
+}
+
+// Raw mode on the alternate screen, restored when dropped
+struct Terminal {
+    // Terminal reporting key releases (kitty keyboard protocol)
+    releases: bool,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        let mut out = io::stdout();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        if releases {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+        }
+        Ok(Self { releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.releases {
+            let _ = execute!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Run `rom` in the terminal until Escape or Ctrl-C is pressed
+pub fn run(rom: &[u8], options: &Options, mut audio: Audio) -> Result<(), String> {
+    let mut chip = Chip8::new(options.quirks);
+    chip.load(rom).map_err(|e| e.to_string())?;
+    let palette = options.palettes.select(options.palette.as_deref())?;
+
+    let terminal = Terminal::enter().map_err(|e| e.to_string())?;
+    let result = run_terminal(&mut chip, options, &palette, &mut audio, terminal.releases);
+    drop(terminal);
+    result.map_err(|e| format!("{}\n{}", e, MachineState::capture(&chip, 0)))
+}
+
+fn run_terminal(
+    chip: &mut Chip8,
+    options: &Options,
+    palette: &Palette,
+    audio: &mut Audio,
+    releases: bool,
+) -> Result<(), String> {
+    let io_error = |e: io::Error| e.to_string();
+    let mut out = io::stdout();
+    let mut ticker = Ticker::new(SystemClock::new());
+    let mut scheduler = Scheduler::new(options.speed);
+    let mut flicker = AntiFlicker::new(options.blend);
+    flicker.push(chip.planes());
+    let mut hold = KeyHold::default();
+    let mut redraw = true;
+    // Planes width and height last drawn, the terminal being cleared when
+    // switching between low and high resolution
+    let mut size = (0, 0);
+
+    loop {
+        while event::poll(Duration::ZERO).map_err(io_error)? {
+            match event::read().map_err(io_error)? {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => return Ok(()),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers,
+                    ..
+                }) if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                Event::Key(event) => {
+                    let Some(key) = key_code(event.code).and_then(|code| options.keymap.key(&code))
+                    else {
+                        continue;
+                    };
+                    match event.kind {
+                        KeyEventKind::Release => chip.set_key(key, false),
+                        _ if releases => chip.set_key(key, true),
+                        _ => {
+                            if hold.press(key) {
+                                chip.set_key(key, true);
+                            }
+                        }
+                    }
+                }
+                Event::Resize(..) => {
+                    queue!(out, terminal::Clear(terminal::ClearType::All)).map_err(io_error)?;
+                    redraw = true;
+                }
+                _ => (),
+            }
+        }
+
+        for _ in 0..ticker.ticks().min(MAX_CATCH_UP_FRAMES) {
+            let frame = scheduler
+                .run_frame(chip)
+                .map_err(|e| format!("crashed: {}", e))?;
+            if frame.exited {
+                return Ok(());
+            }
+            redraw |= frame.drawn;
+            if flicker.mode() != FrameBlend::Off {
+                redraw |= flicker.push(chip.planes());
+            }
+            audio.frame(chip);
+            chip.tick_timers();
+            for key in hold.frame() {
+                chip.set_key(key, false);
+            }
+        }
+
+        if redraw {
+            let planes = match flicker.mode() {
+                FrameBlend::Off => chip.planes(),
+                _ => flicker.output().to_vec(),
+            };
+            let drawn = (planes.first().map_or(0, Vec::len), planes.len());
+            if drawn != size {
+                queue!(out, terminal::Clear(terminal::ClearType::All)).map_err(io_error)?;
+                size = drawn;
+            }
+            draw(&mut out, &planes, palette).map_err(io_error)?;
+            redraw = false;
+        }
+
+        // Sleep until next frame, waking up early on input
+        event::poll(ticker.until_next()).map_err(io_error)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_release_emulation() {
+        let mut hold = KeyHold::default();
+        assert!(hold.press(5));
+        for _ in 1..PRESS_HOLD_FRAMES {
+            assert!(hold.frame().is_empty());
+        }
+        // Repeats keep the key down
+        assert!(!hold.press(5));
+        for _ in 1..REPEAT_HOLD_FRAMES {
+            assert!(hold.frame().is_empty());
+        }
+        assert_eq!(hold.frame(), [5]);
+        assert!(hold.frame().is_empty());
+        assert!(hold.press(5));
+    }
+
+    #[test]
+    fn terminal_keys() {
+        assert_eq!(key_code(KeyCode::Char('q')).as_deref(), Some("KeyQ"));
+        assert_eq!(key_code(KeyCode::Char('Q')).as_deref(), Some("KeyQ"));
+        assert_eq!(key_code(KeyCode::Char('4')).as_deref(), Some("Digit4"));
+        assert_eq!(key_code(KeyCode::Up).as_deref(), Some("ArrowUp"));
+        assert_eq!(key_code(KeyCode::Char('!')), None);
+    }
+
+    #[test]
+    fn half_blocks() {
+        // 2x4 screen: 2 rows of 2 cells
+        let planes = vec![vec![1, 0], vec![1, 1], vec![0, 0], vec![0, 0]];
+        let mut out = Vec::new();
+        draw(&mut out, &planes, &Palette::default()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches('▀').count(), 4);
+        // White on white, then black on white, then black on black for the
+        // whole second row
+        assert_eq!(out.matches("38;2;").count(), 3);
+        assert!(out.starts_with("\x1b[1;1H\x1b[38;2;255;255;255m\x1b[48;2;255;255;255m▀"));
+        assert!(out.contains("\x1b[2;1H"));
+    }
+}
@@ -0,0 +1,336 @@
+use std::time::Instant;
+
+use crate::audio::Audio;
+use crate::chip8::{Chip8, W_HEIGHT, W_WIDTH};
+use crate::debugger::Debugger;
+use crate::flicker::{AntiFlicker, FrameBlend};
+use crate::headless::MachineState;
+use crate::input::{self, InputDevice};
+use crate::options::{Effects, Options};
+use crate::render::Render;
+use crate::rewind::Rewind;
+use crate::scheduler::Scheduler;
+use crate::state::SaveSlots;
+use crate::timer::{SystemClock, Ticker};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey, PhysicalKey},
+    window::WindowBuilder,
+};
+
+// Frames executed at most on a single wake up of the event loop
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+#[cfg(feature = "gamepad")]
+fn gamepad() -> Option<Box<dyn InputDevice>> {
+    match input::Gamepads::new() {
+        Ok(gamepads) => Some(Box::new(gamepads)),
+        Err(e) => {
+            eprintln!("warning: gamepads disabled: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+fn gamepad() -> Option<Box<dyn InputDevice>> {
+    None
+}
+
+// Open a window and run `rom` until the window is closed
+pub fn run(rom: &[u8], options: &Options, mut audio: Audio) -> Result<(), String> {
+    let mut chip = Chip8::new(options.quirks);
+    chip.load(rom).map_err(|e| e.to_string())?;
+
+    let (w_height, w_width) = (
+        (W_HEIGHT * options.scale) as u32,
+        (W_WIDTH * options.scale) as u32,
+    );
+
+    let event_loop = EventLoop::new().map_err(|e| e.to_string())?;
+
+    let window = WindowBuilder::new()
+        .with_title("crab8")
+        .with_inner_size(winit::dpi::LogicalSize::new(w_width, w_height))
+        .build(&event_loop)
+        .map_err(|e| e.to_string())?;
+
+    let keymap = options.keymap.clone();
+    let mut gamepad = gamepad();
+    let palettes = options.palettes.clone();
+    let mut palette = palettes.select(options.palette.as_deref())?;
+    let mut render = pollster::block_on(Render::new(window, palette, options.effects))?;
+    // F2 switches CRT effects on and off, the configured ones or a CRT preset
+    let crt = if options.effects.is_off() {
+        Effects::crt()
+    } else {
+        options.effects
+    };
+
+    // Screen presented when frames are combined, updated on every emulated frame
+    let mut flicker = AntiFlicker::new(options.blend);
+    flicker.push(chip.planes());
+
+    let mut ticker = Ticker::new(SystemClock::new());
+    let mut scheduler = Scheduler::new(options.speed);
+    // Machine is frozen after an error, leaving the last frame on screen
+    let mut crashed = false;
+    // F5 saves into the current slot, F8 loads from it, F6/F7 select the slot
+    let mut slots = SaveSlots::new(options.saves.clone().unwrap_or_else(|| "crab8".into()));
+    // Machine runs backwards, one snapshot per frame, while Backspace is held
+    let mut rewind = Rewind::default();
+    let mut rewinding = false;
+    // F9 pauses or resumes, F10 steps over calls, F11 steps, F12 runs to return
+    let mut debugger = Debugger::new(options.breakpoints.clone());
+    if options.pause {
+        debugger.pause();
+    }
+
+    event_loop
+        .run(move |event, elwt| {
+            match event {
+                Event::WindowEvent { event, window_id }
+                    if window_id == render.window().id() && !render.input(&event) =>
+                {
+                    match event {
+                        WindowEvent::CloseRequested
+                        | WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key: Key::Named(NamedKey::Escape),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        } => elwt.exit(),
+                        WindowEvent::RedrawRequested => {
+                            // Notify the windowing system that we'll be presenting to the window.
+                            let planes = match flicker.mode() {
+                                FrameBlend::Off => chip.planes(),
+                                _ => flicker.output().to_vec(),
+                            };
+                            match render.render(planes) {
+                                Ok(_) => {}
+                                Err(wgpu::SurfaceError::Lost) => render.resize(*render.size()),
+                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                                Err(e) => eprintln!("Unexpeted errror :{:?}", e),
+                            }
+                        }
+                        WindowEvent::Resized(physical_size) => {
+                            render.resize(physical_size);
+                        }
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            // new_inner_size is &&mut so we have to dereference it twice
+                            let inner_size = render.window().inner_size();
+                            render.resize(PhysicalSize {
+                                width: (scale_factor * f64::from(inner_size.width)) as u32,
+                                height: (scale_factor * f64::from(inner_size.height)) as u32,
+                            });
+                        }
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key:
+                                        Key::Named(
+                                            key @ (NamedKey::F5
+                                            | NamedKey::F6
+                                            | NamedKey::F7
+                                            | NamedKey::F8),
+                                        ),
+                                    state: ElementState::Pressed,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let result = match key {
+                                NamedKey::F5 => slots
+                                    .save(&chip)
+                                    .map(|_| format!("saved slot {}", slots.slot())),
+                                NamedKey::F8 => slots.load(&mut chip).map(|_| {
+                                    crashed = false;
+                                    rewind.clear();
+                                    flicker = AntiFlicker::new(flicker.mode());
+                                    flicker.push(chip.planes());
+                                    render.window().request_redraw();
+                                    format!("loaded slot {}", slots.slot())
+                                }),
+                                NamedKey::F6 => {
+                                    slots.previous();
+                                    Ok(format!("slot {}", slots.slot()))
+                                }
+                                _ => {
+                                    slots.next();
+                                    Ok(format!("slot {}", slots.slot()))
+                                }
+                            };
+                            let message = result.unwrap_or_else(|e| {
+                                eprintln!("error: {}", e);
+                                e
+                            });
+                            render.window().set_title(&format!("crab8 - {}", message));
+                        }
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key:
+                                        Key::Named(
+                                            key @ (NamedKey::F9
+                                            | NamedKey::F10
+                                            | NamedKey::F11
+                                            | NamedKey::F12),
+                                        ),
+                                    state: ElementState::Pressed,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => match key {
+                            NamedKey::F9 if debugger.is_paused() => {
+                                debugger.resume();
+                                render.window().set_title("crab8");
+                            }
+                            NamedKey::F9 => debugger.pause(),
+                            NamedKey::F10 => debugger.step_over(&chip),
+                            NamedKey::F11 => debugger.single_step(),
+                            _ => debugger.run_to_return(&chip),
+                        },
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key: Key::Named(NamedKey::F2),
+                                    state: ElementState::Pressed,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let effects = if render.effects().is_off() {
+                                crt
+                            } else {
+                                Effects::default()
+                            };
+                            render.set_effects(effects);
+                            render.window().request_redraw();
+                        }
+                        // F3 cycles through the named palettes
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key: Key::Named(NamedKey::F3),
+                                    state: ElementState::Pressed,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let (name, next) = palettes.next(palette);
+                            palette = next;
+                            render.set_palette(palette);
+                            render
+                                .window()
+                                .set_title(&format!("crab8 - palette {}", name));
+                            render.window().request_redraw();
+                        }
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key: Key::Named(NamedKey::Backspace),
+                                    state,
+                                    ..
+                                },
+                            ..
+                        } => rewinding = state == ElementState::Pressed,
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key: PhysicalKey::Code(code),
+                                    state,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            // Key codes are named after their variants, as in the W3C spec
+                            if let Some(key) = keymap.key(&format!("{:?}", code)) {
+                                chip.set_key(key, state == ElementState::Pressed);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                Event::AboutToWait => {
+                    // Run every frame due since last wake up, without trying to
+                    // catch up after a long stall (e.g. window being dragged)
+                    let mut drawn = false;
+                    if let Some(device) = gamepad.as_mut() {
+                        input::update_keys(device.as_mut(), &keymap, &mut chip);
+                    }
+                    for _ in 0..ticker.ticks().min(MAX_CATCH_UP_FRAMES) {
+                        if rewinding {
+                            if rewind.step_back(&mut chip) {
+                                crashed = false;
+                                drawn = true;
+                                if flicker.mode() != FrameBlend::Off {
+                                    flicker.push(chip.planes());
+                                }
+                            }
+                            audio.frame(&chip);
+                            continue;
+                        }
+                        if crashed {
+                            break;
+                        }
+                        match scheduler.run_frame_with(&mut chip, Some(&mut debugger)) {
+                            Ok(frame) if frame.exited => elwt.exit(),
+                            Ok(frame) => drawn |= frame.drawn,
+                            Err(e) => {
+                                crashed = true;
+                                eprintln!("crashed: {}\n{}", e, MachineState::capture(&chip, 0));
+                                render
+                                    .window()
+                                    .set_title(&format!("crab8 - crashed: {}", e));
+                            }
+                        }
+                        if let Some(stop) = debugger.take_stop() {
+                            eprintln!(
+                                "{} at {:03X}\n{}",
+                                stop,
+                                chip.pc(),
+                                MachineState::capture(&chip, 0)
+                            );
+                            render.window().set_title(&format!(
+                                "crab8 - {} at {:03X}",
+                                stop,
+                                chip.pc()
+                            ));
+                        }
+                        // Combined frames change on the frame after drawing too
+                        if flicker.mode() != FrameBlend::Off {
+                            drawn |= flicker.push(chip.planes());
+                        }
+                        // Timers are frozen while paused
+                        if debugger.is_paused() {
+                            break;
+                        }
+                        audio.frame(&chip);
+                        chip.tick_timers();
+                        rewind.record(&chip);
+                    }
+                    // Phosphor trails keep fading on frames without drawing
+                    if drawn || render.effects().phosphor > 0.0 {
+                        render.window().request_redraw();
+                    }
+
+                    // Sleep until next frame
+                    elwt.set_control_flow(ControlFlow::WaitUntil(
+                        Instant::now() + ticker.until_next(),
+                    ));
+                }
+
+                _ => (),
+            }
+        })
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,143 @@
+// Runs test ROMs headlessly and compares the final screen with golden
+// framebuffers stored in tests/golden, as `#` and `.` rows.
+//
+// The Timendus suite (https://github.com/Timendus/chip8-test-suite) is not
+// distributed with crab8, so its cases are ignored: copy the ROMs into
+// roms/timendus and run `cargo test --test conformance -- --include-ignored`.
+// Run with CRAB8_BLESS=1 to write the golden files from the current output,
+// then check that they show the pass screens of the suite. The Timendus golden
+// files are not committed yet, as they still have to be checked that way.
+
+use std::{env, fs, path::Path};
+
+use crab8::{headless::Headless, Options, Quirks};
+
+struct Case {
+    name: &'static str,
+    rom: &'static str,
+    cycles: usize,
+    quirks: Quirks,
+    // Value at 0x1FF, which Timendus ROMs read to skip their menu
+    menu: Option<u8>,
+}
+
+impl Case {
+    fn new(name: &'static str, rom: &'static str, cycles: usize) -> Self {
+        Self {
+            name,
+            rom,
+            cycles,
+            quirks: Quirks::modern(),
+            menu: None,
+        }
+    }
+
+    fn quirks(self, quirks: Quirks) -> Self {
+        Self { quirks, ..self }
+    }
+
+    fn menu(self, choice: u8) -> Self {
+        Self {
+            menu: Some(choice),
+            ..self
+        }
+    }
+}
+
+fn screen(headless: &Headless) -> String {
+    headless
+        .chip()
+        .pixels()
+        .iter()
+        .map(|row| {
+            let mut line: String = row.iter().map(|&p| if p { '#' } else { '.' }).collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+fn check(case: Case) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let rom_path = root.join(case.rom);
+    let rom =
+        fs::read(&rom_path).unwrap_or_else(|e| panic!("cannot read {}: {}", rom_path.display(), e));
+
+    let options = Options {
+        quirks: case.quirks,
+        ..Options::default()
+    };
+    let mut headless = Headless::with_options(&rom, &options).unwrap();
+    if let Some(choice) = case.menu {
+        headless.chip_mut().write_memory(0x1ff, choice).unwrap();
+    }
+    headless
+        .run_cycles(case.cycles)
+        .unwrap_or_else(|e| panic!("{} crashed: {}", case.name, e));
+    let actual = screen(&headless);
+
+    let golden_path = root.join("tests/golden").join(format!("{}.txt", case.name));
+    if env::var_os("CRAB8_BLESS").is_some() {
+        fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden_path).unwrap_or_else(|e| {
+        panic!(
+            "cannot read {}: {}, run with CRAB8_BLESS=1 to create it",
+            golden_path.display(),
+            e
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} screen differs from {}:\n{}",
+        case.name,
+        golden_path.display(),
+        actual
+    );
+}
+
+#[test]
+fn ibm_logo() {
+    check(Case::new("ibm_logo", "roms/ibm_logo.ch8", 100));
+}
+
+#[test]
+fn test_opcode() {
+    check(Case::new("test_opcode", "roms/test_opcode.ch8", 1000));
+}
+
+#[test]
+#[ignore = "needs roms/timendus"]
+fn corax_plus() {
+    check(Case::new("corax_plus", "roms/timendus/3-corax+.ch8", 1000));
+}
+
+#[test]
+#[ignore = "needs roms/timendus"]
+fn flags() {
+    check(Case::new("flags", "roms/timendus/4-flags.ch8", 1000));
+}
+
+#[test]
+#[ignore = "needs roms/timendus"]
+fn quirks_chip8() {
+    check(
+        Case::new("quirks_chip8", "roms/timendus/5-quirks.ch8", 50_000)
+            .quirks(Quirks::cosmac_vip())
+            .menu(1),
+    );
+}
+
+#[test]
+#[ignore = "needs roms/timendus"]
+fn quirks_xochip() {
+    check(Case::new("quirks_xochip", "roms/timendus/5-quirks.ch8", 50_000).menu(3));
+}
+
+#[test]
+#[ignore = "needs roms/timendus"]
+fn keypad() {
+    // EX9E and EXA1 test, with no key pressed
+    check(Case::new("keypad", "roms/timendus/6-keypad.ch8", 1000).menu(1));
+}